@@ -0,0 +1,179 @@
+//! Exact conversions between decimal strings and 16.16, using only integer arithmetic.
+
+use super::Overflow;
+use std::error::Error;
+use std::fmt;
+
+// Digits needed to write out any 16.16 fraction exactly, since 2^-16 = 5^16 / 10^16.
+const FRAC_DIGITS: usize = 16;
+const FRAC_SCALE: u64 = 152_587_890_625;
+// Fraction digits parse_fix scales exactly. Any past these can only break a tie.
+const PARSE_DIGITS: usize = 20;
+
+// Why a string couldn't be read as a 16.16 value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseFix16Error {
+    // The string isn't a decimal number.
+    Invalid,
+    // The number is outside the range of 16.16.
+    OutOfRange,
+}
+
+impl fmt::Display for ParseFix16Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseFix16Error::Invalid => write!(f, "invalid 16.16 literal"),
+            ParseFix16Error::OutOfRange => write!(f, "number out of range for 16.16"),
+        }
+    }
+}
+
+impl Error for ParseFix16Error {}
+
+// Reads a decimal number such as "-12.375", rounding to the nearest 16.16 value with ties to even.
+pub fn parse_fix(s: &str) -> Result<i32, ParseFix16Error> {
+    let (negative, unsigned) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (int_str, frac_str) = match unsigned.find('.') {
+        Some(i) => (&unsigned[..i], &unsigned[i + 1..]),
+        None => (unsigned, ""),
+    };
+    let all_digits = |part: &str| part.bytes().all(|c| c.is_ascii_digit());
+    if (int_str.is_empty() && frac_str.is_empty()) || !all_digits(int_str) || !all_digits(frac_str)
+    {
+        return Err(ParseFix16Error::Invalid);
+    }
+    let mut int: u64 = 0;
+    for c in int_str.bytes() {
+        int = int * 10 + u64::from(c - b'0');
+        if int > 1 << 15 {
+            return Err(ParseFix16Error::OutOfRange);
+        }
+    }
+    let (head, tail) = frac_str.split_at(frac_str.len().min(PARSE_DIGITS));
+    let mut num: u128 = 0;
+    for c in head.bytes() {
+        num = num * 10 + u128::from(c - b'0');
+    }
+    let den = 10u128.pow(head.len() as u32);
+    let sticky = tail.bytes().any(|c| c != b'0');
+    let scaled = num << 16;
+    let (q, r) = ((scaled / den) as u64, scaled % den);
+    let round_up = 2 * r > den || (2 * r == den && (sticky || q % 2 == 1));
+    let magnitude = ((int << 16) + q + round_up as u64) as i64;
+    let bits = if negative { -magnitude } else { magnitude };
+    super::narrow(bits, Overflow::Trapping).ok_or(ParseFix16Error::OutOfRange)
+}
+
+// Writes a 16.16 value in decimal. With no precision all the digits needed to give the exact
+// value are written, otherwise it is rounded half to even to that many decimals.
+pub fn format_fix(a: i32, precision: Option<usize>) -> String {
+    let body = format_magnitude(a, precision);
+    if a < 0 {
+        format!("-{}", body)
+    } else {
+        body
+    }
+}
+
+// Like format_fix, but without the sign.
+pub(super) fn format_magnitude(a: i32, precision: Option<usize>) -> String {
+    let magnitude = i64::from(a).unsigned_abs();
+    let mut int = magnitude >> 16;
+    // The fraction in units of 10^-16, which is always exact.
+    let mut frac = (magnitude & 0xffff) * FRAC_SCALE;
+    // Digits of frac to write, and zeros to add past the exact ones.
+    let (digits, padding) = match precision {
+        Some(p) if p < FRAC_DIGITS => {
+            let unit = 10u64.pow((FRAC_DIGITS - p) as u32);
+            let (q, r) = (frac / unit, frac % unit);
+            let round_up = 2 * r > unit || (2 * r == unit && q % 2 == 1);
+            frac = q + round_up as u64;
+            if frac == 10u64.pow(p as u32) {
+                int += 1;
+                frac = 0;
+            }
+            (p, 0)
+        }
+        Some(p) => (FRAC_DIGITS, p - FRAC_DIGITS),
+        None if frac == 0 => (0, 0),
+        None => {
+            let mut digits = FRAC_DIGITS;
+            while frac.is_multiple_of(10) {
+                frac /= 10;
+                digits -= 1;
+            }
+            (digits, 0)
+        }
+    };
+    if digits == 0 {
+        int.to_string()
+    } else {
+        format!(
+            "{}.{:0>width$}{}",
+            int,
+            frac,
+            "0".repeat(padding),
+            width = digits
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_fix(0, None), "0");
+        assert_eq!(format_fix(-12 << 16, None), "-12");
+        assert_eq!(format_fix(0x18000, None), "1.5");
+        assert_eq!(format_fix(1, None), "0.0000152587890625");
+        assert_eq!(format_fix(i32::MAX, None), "32767.9999847412109375");
+        assert_eq!(format_fix(i32::MIN, None), "-32768");
+        assert_eq!(format_fix(-0x32000, Some(2)), "-3.12");
+        assert_eq!(format_fix(0x36000, Some(2)), "3.38");
+        assert_eq!(format_fix(0x28000, Some(0)), "2");
+        assert_eq!(format_fix(i32::MAX, Some(3)), "32768.000");
+        assert_eq!(format_fix(1, Some(18)), "0.000015258789062500");
+        // Values above 256 keep their low bits, which f32 would drop.
+        assert_eq!(
+            format_fix((30000 << 16) + 1, None),
+            "30000.0000152587890625"
+        );
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_fix("0"), Ok(0));
+        assert_eq!(parse_fix("-12.375"), Ok(-0xc6000));
+        assert_eq!(parse_fix("+.5"), Ok(0x8000));
+        assert_eq!(parse_fix("7."), Ok(7 << 16));
+        assert_eq!(parse_fix("32767.9999847412109375"), Ok(i32::MAX));
+        assert_eq!(parse_fix("-32768"), Ok(i32::MIN));
+        // Half an LSB rounds to even, anything past it rounds up.
+        assert_eq!(parse_fix("0.00000762939453125"), Ok(0));
+        assert_eq!(parse_fix("0.0000228881835937500"), Ok(2));
+        assert_eq!(parse_fix("0.00000762939453125000000001"), Ok(1));
+        assert_eq!(parse_fix("30000.0000152587890625"), Ok((30000 << 16) + 1));
+        assert_eq!(parse_fix("32768"), Err(ParseFix16Error::OutOfRange));
+        assert_eq!(parse_fix("-32768.00001"), Err(ParseFix16Error::OutOfRange));
+        assert_eq!(parse_fix("99999999999"), Err(ParseFix16Error::OutOfRange));
+        for bad in ["", "-", ".", "1.2.3", "1e5", " 1", "0x10", "--1"].iter() {
+            assert_eq!(parse_fix(bad), Err(ParseFix16Error::Invalid), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for bits in (i32::MIN..=i32::MAX).step_by(0x10001) {
+            assert_eq!(parse_fix(&format_fix(bits, None)), Ok(bits));
+        }
+        for bits in [1, -1, 0x7fff_0001, i32::MAX, i32::MIN].iter() {
+            assert_eq!(parse_fix(&format_fix(*bits, None)), Ok(*bits));
+        }
+    }
+}
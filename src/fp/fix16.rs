@@ -0,0 +1,251 @@
+//! A 16.16 fixed point value type for host code.
+
+use super::{Overflow, ParseFix16Error, Rounding, FP_ONE};
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+// A two's complement 16.16 fixed point value, as held on the vm's stacks and ports.
+//
+// The operators wrap on overflow and round down like the vm's default configuration, and division
+// by zero panics. Use the checked and saturating methods for other behavior.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fix16(i32);
+
+impl Fix16 {
+    pub const ZERO: Fix16 = Fix16(0);
+    pub const ONE: Fix16 = Fix16(FP_ONE);
+    pub const MAX: Fix16 = Fix16(i32::MAX);
+    pub const MIN: Fix16 = Fix16(i32::MIN);
+    // The smallest positive value.
+    pub const EPSILON: Fix16 = Fix16(1);
+
+    pub const fn from_bits(bits: i32) -> Fix16 {
+        Fix16(bits)
+    }
+
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    pub fn from_f32(a: f32) -> Fix16 {
+        Fix16(super::float_to_fix(a))
+    }
+
+    pub fn to_f32(self) -> f32 {
+        super::fix_to_float(self.0)
+    }
+
+    // Returns None for NaN and for values outside the range of 16.16.
+    pub fn from_f64(a: f64) -> Option<Fix16> {
+        super::f64_to_fix(a).map(Fix16)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        super::fix_to_f64(self.0)
+    }
+
+    pub fn checked_add(self, rhs: Fix16) -> Option<Fix16> {
+        self.0.checked_add(rhs.0).map(Fix16)
+    }
+
+    pub fn checked_sub(self, rhs: Fix16) -> Option<Fix16> {
+        self.0.checked_sub(rhs.0).map(Fix16)
+    }
+
+    pub fn checked_mul(self, rhs: Fix16) -> Option<Fix16> {
+        let wide = super::fp_mul_wide(self.0, rhs.0, Rounding::Floor);
+        super::narrow(wide, Overflow::Trapping).map(Fix16)
+    }
+
+    pub fn checked_div(self, rhs: Fix16) -> Option<Fix16> {
+        super::fp_div_checked(self.0, rhs.0).map(Fix16)
+    }
+
+    pub fn checked_neg(self) -> Option<Fix16> {
+        self.0.checked_neg().map(Fix16)
+    }
+
+    pub fn saturating_add(self, rhs: Fix16) -> Fix16 {
+        Fix16(self.0.saturating_add(rhs.0))
+    }
+
+    pub fn saturating_sub(self, rhs: Fix16) -> Fix16 {
+        Fix16(self.0.saturating_sub(rhs.0))
+    }
+
+    pub fn saturating_mul(self, rhs: Fix16) -> Fix16 {
+        let wide = super::fp_mul_wide(self.0, rhs.0, Rounding::Floor);
+        Fix16(super::narrow(wide, Overflow::Saturating).unwrap())
+    }
+
+    // Division by zero saturates like DivZero::Saturate.
+    pub fn saturating_div(self, rhs: Fix16) -> Fix16 {
+        if rhs.0 == 0 {
+            return Fix16(super::fp_div_zero_saturated(self.0));
+        }
+        let wide = super::fp_div_wide(self.0, rhs.0, Rounding::Floor);
+        Fix16(super::narrow(wide, Overflow::Saturating).unwrap())
+    }
+
+    pub fn saturating_neg(self) -> Fix16 {
+        Fix16(self.0.saturating_neg())
+    }
+}
+
+impl From<i16> for Fix16 {
+    fn from(a: i16) -> Fix16 {
+        Fix16(i32::from(a) << 16)
+    }
+}
+
+impl Add for Fix16 {
+    type Output = Fix16;
+
+    fn add(self, rhs: Fix16) -> Fix16 {
+        Fix16(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl Sub for Fix16 {
+    type Output = Fix16;
+
+    fn sub(self, rhs: Fix16) -> Fix16 {
+        Fix16(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl Mul for Fix16 {
+    type Output = Fix16;
+
+    fn mul(self, rhs: Fix16) -> Fix16 {
+        Fix16(super::fp_mul(self.0, rhs.0))
+    }
+}
+
+impl Div for Fix16 {
+    type Output = Fix16;
+
+    fn div(self, rhs: Fix16) -> Fix16 {
+        if rhs.0 == 0 {
+            panic!("attempt to divide by zero");
+        }
+        Fix16(super::fp_div(self.0, rhs.0))
+    }
+}
+
+impl Neg for Fix16 {
+    type Output = Fix16;
+
+    fn neg(self) -> Fix16 {
+        Fix16(self.0.wrapping_neg())
+    }
+}
+
+// Writes the exact decimal value, or rounds it half to even when a precision is given, so
+// format!("{:.2}", x) gives two decimals.
+impl fmt::Display for Fix16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let body = super::decimal::format_magnitude(self.0, f.precision());
+        f.pad_integral(self.0 >= 0, "", &body)
+    }
+}
+
+// Reads a decimal number such as "-12.375", rounding to the nearest value with ties to even.
+impl FromStr for Fix16 {
+    type Err = ParseFix16Error;
+
+    fn from_str(s: &str) -> Result<Fix16, ParseFix16Error> {
+        super::parse_fix(s).map(Fix16)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bits() {
+        assert_eq!(Fix16::from_bits(0x18000).to_bits(), 0x18000);
+        assert_eq!(Fix16::from(3), Fix16::from_bits(3 << 16));
+        assert_eq!(Fix16::from(-1), -Fix16::ONE);
+        assert_eq!(Fix16::from_f32(1.5).to_f32(), 1.5);
+        assert_eq!(Fix16::from_f64(-0.25), Some(Fix16::from_bits(-0x4000)));
+        assert_eq!(Fix16::from_f64(1e6), None);
+        assert_eq!(Fix16::MAX.to_f64(), 32767.0 + 65535.0 / 65536.0);
+        assert_eq!(Fix16::default(), Fix16::ZERO);
+        assert!(Fix16::from(-2) < Fix16::EPSILON);
+    }
+
+    #[test]
+    fn operators() {
+        let a = Fix16::from_f32(2.5);
+        let b = Fix16::from_f32(-1.25);
+        assert_eq!(a + b, Fix16::from_f32(1.25));
+        assert_eq!(a - b, Fix16::from_f32(3.75));
+        assert_eq!(a * b, Fix16::from_f32(-3.125));
+        assert_eq!(a / b, Fix16::from(-2));
+        assert_eq!(-a, Fix16::from_f32(-2.5));
+        // Wrapping, like the vm's default.
+        assert_eq!(Fix16::MAX + Fix16::EPSILON, Fix16::MIN);
+        assert_eq!(-Fix16::MIN, Fix16::MIN);
+    }
+
+    #[test]
+    #[should_panic(expected = "divide by zero")]
+    fn divide_by_zero() {
+        let _ = Fix16::ONE / Fix16::ZERO;
+    }
+
+    #[test]
+    fn checked() {
+        let big = Fix16::from(20000);
+        assert_eq!(big.checked_add(big), None);
+        assert_eq!(Fix16::MIN.checked_sub(Fix16::EPSILON), None);
+        assert_eq!(big.checked_mul(Fix16::from(2)), None);
+        assert_eq!(big.checked_div(Fix16::from_f32(0.5)), None);
+        assert_eq!(big.checked_div(Fix16::ZERO), None);
+        assert_eq!(Fix16::MIN.checked_neg(), None);
+        assert_eq!(Fix16::ONE.checked_add(Fix16::ONE), Some(Fix16::from(2)));
+        assert_eq!(big.checked_div(Fix16::from(-2)), Some(Fix16::from(-10000)));
+    }
+
+    #[test]
+    fn saturating() {
+        let big = Fix16::from(20000);
+        assert_eq!(big.saturating_add(big), Fix16::MAX);
+        assert_eq!((-big).saturating_sub(big), Fix16::MIN);
+        assert_eq!(big.saturating_mul(Fix16::from(-2)), Fix16::MIN);
+        assert_eq!(big.saturating_div(Fix16::from_f32(0.5)), Fix16::MAX);
+        assert_eq!((-big).saturating_div(Fix16::ZERO), Fix16::MIN);
+        assert_eq!(Fix16::MIN.saturating_neg(), Fix16::MAX);
+        assert_eq!(Fix16::ONE.saturating_mul(Fix16::ONE), Fix16::ONE);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Fix16::from_f32(1.5).to_string(), "1.5");
+        assert_eq!(Fix16::MIN.to_string(), "-32768");
+        assert_eq!(format!("{:.2}", Fix16::from_f32(-3.125)), "-3.12");
+        assert_eq!(format!("{:.1}", Fix16::ONE), "1.0");
+        assert_eq!(format!("{:>6.1}", Fix16::from(-3)), "  -3.0");
+        assert_eq!(format!("{:+}", Fix16::ONE), "+1");
+        assert_eq!(format!("{:06.2}", Fix16::from_f32(-1.5)), "-01.50");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("-12.375".parse(), Ok(Fix16::from_f32(-12.375)));
+        assert_eq!("0.0000152587890625".parse(), Ok(Fix16::EPSILON));
+        assert_eq!("32768".parse::<Fix16>(), Err(ParseFix16Error::OutOfRange));
+        assert_eq!("1e5".parse::<Fix16>(), Err(ParseFix16Error::Invalid));
+    }
+
+    #[test]
+    fn round_trip() {
+        for bits in [0, 1, -1, 0x1234_5678, -0x7654_3210, i32::MAX, i32::MIN].iter() {
+            let a = Fix16::from_bits(*bits);
+            assert_eq!(a.to_string().parse(), Ok(a));
+        }
+    }
+}
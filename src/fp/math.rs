@@ -0,0 +1,326 @@
+//! Square root and transcendental functions on 16.16 values, using only integer arithmetic.
+//!
+//! Unless noted otherwise each result is within one LSB (2^-16) of the exact value. Angles are
+//! in radians.
+
+// atan(2^-i) in 2.30, for the CORDIC iterations.
+const ATAN_TABLE: [i64; 31] = [
+    843_314_857,
+    497_837_829,
+    263_043_837,
+    133_525_159,
+    67_021_687,
+    33_543_516,
+    16_775_851,
+    8_388_437,
+    4_194_283,
+    2_097_149,
+    1_048_576,
+    524_288,
+    262_144,
+    131_072,
+    65_536,
+    32_768,
+    16_384,
+    8_192,
+    4_096,
+    2_048,
+    1_024,
+    512,
+    256,
+    128,
+    64,
+    32,
+    16,
+    8,
+    4,
+    2,
+    1,
+];
+// 1 / the CORDIC gain, in 2.30.
+const CORDIC_K: i64 = 652_032_874;
+const PI_Q30: i64 = 3_373_259_426;
+const PI_Q32: i64 = 13_493_037_705;
+const LN2_Q60: i128 = 799_144_290_325_165_979;
+const LOG2E_Q60: i128 = 1_663_314_137_230_540_311;
+const ONE_Q60: i128 = 1 << 60;
+
+// The square root rounded to the nearest value. Returns None for negative values.
+pub fn fp_sqrt(a: i32) -> Option<i32> {
+    if a < 0 {
+        return None;
+    }
+    // sqrt(a * 2^-16) * 2^16 = sqrt(a * 2^16).
+    let n = (a as u64) << 16;
+    let r = isqrt(n);
+    // n is an integer, so it is past (r + 0.5)^2 exactly when it is past r^2 + r.
+    let rounded = if n - r * r > r { r + 1 } else { r };
+    Some(rounded as i32)
+}
+
+pub fn fp_sin(a: i32) -> i32 {
+    sin_cos(a).0
+}
+
+pub fn fp_cos(a: i32) -> i32 {
+    sin_cos(a).1
+}
+
+// The angle of the point (x, y) from the positive x axis, in (-pi, pi]. atan2(0, 0) is 0.
+pub fn fp_atan2(y: i32, x: i32) -> i32 {
+    if x == 0 && y == 0 {
+        return 0;
+    }
+    let (mut x, mut y) = (i64::from(x), i64::from(y));
+    // Scale up so small vectors keep their precision through the shifts below.
+    let shift = x.abs().max(y.abs()).leading_zeros() as i64 - 23;
+    x <<= shift;
+    y <<= shift;
+    // Vectoring only converges in the right half plane, so turn the point half a circle first.
+    let mut z = 0;
+    if x < 0 {
+        z = if y < 0 { -PI_Q30 } else { PI_Q30 };
+        x = -x;
+        y = -y;
+    }
+    for (i, atan) in ATAN_TABLE.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+        if y < 0 {
+            x -= dx;
+            y += dy;
+            z -= atan;
+        } else {
+            x += dx;
+            y -= dy;
+            z += atan;
+        }
+    }
+    q30_to_fix(z)
+}
+
+// e^a without losing a result that doesn't fit in 16.16. Results too large for i64 saturate.
+pub fn fp_exp_wide(a: i32) -> i64 {
+    // e^a = 2^t with t = a * log2(e), split into an integer part k and a fraction f.
+    let t = (i128::from(a) * LOG2E_Q60) >> 16;
+    let k = (t >> 60) as i64;
+    let f = t - (i128::from(k) << 60);
+    // 2^f = e^(f * ln 2), which the series gives to full precision for f in [0, 1).
+    let m = exp_series((f * LN2_Q60) >> 60);
+    // m is in [1, 2) in 4.60, so k past this overflows i64 and k below -60 rounds to 0.
+    let shift = 44 - k;
+    if shift < -2 {
+        i64::MAX
+    } else if shift <= 0 {
+        (m << -shift) as i64
+    } else if shift > 62 {
+        0
+    } else {
+        ((m + (1 << (shift - 1))) >> shift) as i64
+    }
+}
+
+// The natural logarithm. Returns None for values that are zero or negative.
+pub fn fp_log(a: i32) -> Option<i32> {
+    if a <= 0 {
+        return None;
+    }
+    // a = m * 2^p with m in [1, 2), so ln(a * 2^-16) = ln(m) + (p - 16) * ln(2).
+    let p = 31 - a.leading_zeros() as i32;
+    let m = i128::from(a) << (60 - p);
+    // ln(m) = 2 * atanh(s) with s = (m - 1) / (m + 1), which is below 1/3.
+    let s = ((m - ONE_Q60) << 60) / (m + ONE_Q60);
+    let s2 = (s * s) >> 60;
+    let mut sum = 0;
+    let mut power = s;
+    let mut n = 1;
+    while power != 0 {
+        sum += power / n;
+        power = (power * s2) >> 60;
+        n += 2;
+    }
+    let ln = 2 * sum + i128::from(p - 16) * LN2_Q60;
+    Some(((ln + (1 << 43)) >> 44) as i32)
+}
+
+// Both the sine and the cosine of a.
+fn sin_cos(a: i32) -> (i32, i32) {
+    // Bring the angle into [-pi, pi) and then into [-pi/2, pi/2], where CORDIC converges.
+    let mut r = (i64::from(a) << 16).rem_euclid(2 * PI_Q32);
+    if r >= PI_Q32 {
+        r -= 2 * PI_Q32;
+    }
+    let mut cos_sign = 1;
+    if r > PI_Q32 / 2 {
+        r = PI_Q32 - r;
+        cos_sign = -1;
+    } else if r < -PI_Q32 / 2 {
+        r = -PI_Q32 - r;
+        cos_sign = -1;
+    }
+    // Rotate (K, 0) by r, which leaves (cos r, sin r) after the gain is removed.
+    let (mut x, mut y, mut z) = (CORDIC_K, 0, r >> 2);
+    for (i, atan) in ATAN_TABLE.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+        if z >= 0 {
+            x -= dx;
+            y += dy;
+            z -= atan;
+        } else {
+            x += dx;
+            y -= dy;
+            z += atan;
+        }
+    }
+    (q30_to_fix(y), cos_sign * q30_to_fix(x))
+}
+
+// e^x for x in [0, 1) in 4.60, by its Taylor series.
+fn exp_series(x: i128) -> i128 {
+    let mut sum = ONE_Q60;
+    let mut term = ONE_Q60;
+    let mut n = 1;
+    while term != 0 {
+        term = ((term * x) >> 60) / n;
+        sum += term;
+        n += 1;
+    }
+    sum
+}
+
+// Rounds a 2.30 value to 16.16.
+fn q30_to_fix(a: i64) -> i32 {
+    ((a + (1 << 13)) >> 14) as i32
+}
+
+// The integer square root, rounded down.
+fn isqrt(n: u64) -> u64 {
+    let mut rem = n;
+    let mut root = 0;
+    let mut bit = 1 << 62;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rem >= root + bit {
+            rem -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fp::{f64_to_fix, fix_to_f64};
+
+    fn assert_within_lsb(result: i32, exact: f64) {
+        let error = (fix_to_f64(result) - exact).abs() * 65536.0;
+        assert!(
+            error <= 1.0,
+            "{} is {} LSBs from {}",
+            fix_to_f64(result),
+            error,
+            exact
+        );
+    }
+
+    // A spread of 16.16 values across the whole range.
+    fn samples() -> impl Iterator<Item = i32> {
+        (i32::MIN..=i32::MAX)
+            .step_by(0x0010_0001)
+            .chain([0, 1, -1, i32::MAX, i32::MIN].iter().cloned())
+    }
+
+    #[test]
+    fn sqrt() {
+        assert_eq!(fp_sqrt(4 << 16), Some(2 << 16));
+        assert_eq!(fp_sqrt(0), Some(0));
+        assert_eq!(fp_sqrt(-1), None);
+        assert_eq!(fp_sqrt(i32::MIN), None);
+        for a in samples().filter(|a| *a >= 0) {
+            let exact = fix_to_f64(a).sqrt();
+            assert_eq!(fp_sqrt(a), f64_to_fix(exact), "sqrt({})", fix_to_f64(a));
+        }
+    }
+
+    #[test]
+    fn sin_cos() {
+        assert_eq!(fp_sin(0), 0);
+        assert_eq!(fp_cos(0), 1 << 16);
+        for a in samples() {
+            let x = fix_to_f64(a);
+            assert_within_lsb(fp_sin(a), x.sin());
+            assert_within_lsb(fp_cos(a), x.cos());
+        }
+        // Either side of the quadrant boundaries.
+        for a in (-0x70000..0x70000).step_by(0x101) {
+            let x = fix_to_f64(a);
+            assert_within_lsb(fp_sin(a), x.sin());
+            assert_within_lsb(fp_cos(a), x.cos());
+        }
+    }
+
+    #[test]
+    fn atan2() {
+        assert_eq!(fp_atan2(0, 0), 0);
+        assert_eq!(fp_atan2(0, -1), f64_to_fix(std::f64::consts::PI).unwrap());
+        assert_eq!(
+            fp_atan2(-1, 0),
+            f64_to_fix(-std::f64::consts::FRAC_PI_2).unwrap()
+        );
+        let values = [
+            0,
+            1,
+            -1,
+            3,
+            0x8000,
+            -0x1_0000,
+            0x2_4000,
+            -0x7_1234,
+            0x100_0000,
+            i32::MAX,
+            i32::MIN,
+        ];
+        for y in values.iter() {
+            for x in values.iter() {
+                let exact = fix_to_f64(*y).atan2(fix_to_f64(*x));
+                assert_within_lsb(fp_atan2(*y, *x), exact);
+            }
+        }
+    }
+
+    #[test]
+    fn exp() {
+        assert_eq!(fp_exp_wide(0), 1 << 16);
+        assert_eq!(fp_exp_wide(i32::MIN), 0);
+        assert_eq!(fp_exp_wide(i32::MAX), i64::MAX);
+        for a in (-0x10_0000..0x0c_0000).step_by(0x123) {
+            let exact = fix_to_f64(a).exp();
+            let wide = fp_exp_wide(a);
+            assert!(
+                (wide as f64 / 65536.0 - exact).abs() <= 1.0 / 65536.0 + exact * 1e-12,
+                "exp({})",
+                fix_to_f64(a)
+            );
+        }
+        // Past ln(32768) the result no longer fits in 16.16.
+        assert!(fp_exp_wide(0xb_0000) > i64::from(i32::MAX));
+    }
+
+    #[test]
+    fn log() {
+        assert_eq!(fp_log(1 << 16), Some(0));
+        assert_eq!(fp_log(0), None);
+        assert_eq!(fp_log(-1 << 16), None);
+        for a in samples().filter(|a| *a > 0) {
+            assert_within_lsb(fp_log(a).unwrap(), fix_to_f64(a).ln());
+        }
+        for a in 1..0x1000 {
+            assert_within_lsb(fp_log(a).unwrap(), fix_to_f64(a).ln());
+        }
+    }
+}
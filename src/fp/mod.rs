@@ -0,0 +1,571 @@
+//! Module of fixed point arithmetic methods for the virtual machine.
+//! SeqStk uses two's complement 16.16 bit fixed point arithmetic.
+
+mod decimal;
+mod fix16;
+mod math;
+
+pub use decimal::{format_fix, parse_fix, ParseFix16Error};
+pub use fix16::Fix16;
+pub use math::{fp_atan2, fp_cos, fp_exp_wide, fp_log, fp_sin, fp_sqrt};
+use std::convert::TryFrom;
+
+const FP_ONE: i32 = 1 << 16;
+const FP_LSB: f32 = 1.0 / ((1 << 16) as f32);
+
+pub fn float_to_fix(a: f32) -> i32 {
+    (a / FP_LSB) as i32
+}
+
+pub fn fix_to_float(a: i32) -> f32 {
+    a as f32 * FP_LSB
+}
+
+// Converts to the nearest 16.16 value, with ties to even. Returns None for NaN and for values
+// outside the range of 16.16.
+pub fn f64_to_fix(a: f64) -> Option<i32> {
+    let scaled = a * f64::from(FP_ONE);
+    // Rounding can only carry a value up to the next integer, so check after it.
+    let rounded = round_half_even(scaled);
+    if rounded >= f64::from(i32::MIN) && rounded <= f64::from(i32::MAX) {
+        Some(rounded as i32)
+    } else {
+        None
+    }
+}
+
+// Converts to f64, which holds every 16.16 value exactly.
+pub fn fix_to_f64(a: i32) -> f64 {
+    f64::from(a) / f64::from(FP_ONE)
+}
+
+// Rounds to the nearest integer, with ties to even. NaN stays NaN.
+fn round_half_even(a: f64) -> f64 {
+    let r = a.round();
+    if (r - a).abs() == 0.5 && r % 2.0 != 0.0 {
+        r - a.signum()
+    } else {
+        r
+    }
+}
+
+// How to round a result that falls between two representable values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    // Round toward negative infinity, like an arithmetic right shift.
+    Floor,
+    // Drop the bits that don't fit, rounding toward zero.
+    Truncate,
+    // Round to the nearest value, and to the even one on a tie.
+    HalfEven,
+    // Round to the nearest value, and away from zero on a tie.
+    HalfAwayFromZero,
+}
+
+// What to do with a result too large for 16.16.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    // Keep the low 32 bits.
+    Wrapping,
+    // Clamp to the largest or smallest 16.16 value.
+    Saturating,
+    // Report the overflow instead of producing a value.
+    Trapping,
+}
+
+// What DIV and MOD do with a zero divisor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DivZero {
+    // Raise a divide by zero fault.
+    Fault,
+    // Produce the largest value with the dividend's sign, or 0 for 0 / 0. The remainders do too.
+    Saturate,
+}
+
+pub fn fp_mul(a: i32, b: i32) -> i32 {
+    fp_mul_rounded(a, b, Rounding::Floor)
+}
+
+// Divides a by b, returning 0 if b is 0.
+pub fn fp_div(a: i32, b: i32) -> i32 {
+    fp_div_rounded(a, b, Rounding::Floor)
+}
+
+// Divides a by b, returning None if b is 0 or the result doesn't fit in 16.16.
+pub fn fp_div_checked(a: i32, b: i32) -> Option<i32> {
+    if b == 0 {
+        None
+    } else {
+        i32::try_from(fp_div_wide(a, b, Rounding::Floor)).ok()
+    }
+}
+
+// The saturated result DivZero::Saturate gives for dividing a by 0.
+pub fn fp_div_zero_saturated(a: i32) -> i32 {
+    match a {
+        0 => 0,
+        a if a > 0 => i32::MAX,
+        _ => i32::MIN,
+    }
+}
+
+pub fn fp_mul_rounded(a: i32, b: i32, mode: Rounding) -> i32 {
+    fp_mul_wide(a, b, mode) as i32
+}
+
+// Divides a by b, returning 0 if b is 0.
+pub fn fp_div_rounded(a: i32, b: i32, mode: Rounding) -> i32 {
+    fp_div_wide(a, b, mode) as i32
+}
+
+// Multiplies without losing a result that doesn't fit in 16.16.
+pub fn fp_mul_wide(a: i32, b: i32, mode: Rounding) -> i64 {
+    round_div(i64::from(a) * i64::from(b), i64::from(FP_ONE), mode)
+}
+
+// Divides without losing a result that doesn't fit in 16.16, returning 0 if b is 0.
+pub fn fp_div_wide(a: i32, b: i32, mode: Rounding) -> i64 {
+    if b == 0 {
+        0
+    } else {
+        round_div(i64::from(a) << 16, i64::from(b), mode)
+    }
+}
+
+// Brings a wide result back to 16.16. Returns None if it doesn't fit and the policy is Trapping.
+pub fn narrow(a: i64, policy: Overflow) -> Option<i32> {
+    match policy {
+        Overflow::Wrapping => Some(a as i32),
+        Overflow::Saturating => Some(a.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32),
+        Overflow::Trapping => i32::try_from(a).ok(),
+    }
+}
+
+// The remainder of a / b with the quotient truncated toward zero, so it takes the sign of a.
+// Returns None if b is 0. i32::MIN % -1 is 0.
+pub fn fp_mod(a: i32, b: i32) -> Option<i32> {
+    if b == 0 {
+        None
+    } else {
+        Some(a.wrapping_rem(b))
+    }
+}
+
+// Negates without losing the one result that doesn't fit in 16.16, -i32::MIN.
+pub fn fp_neg_wide(a: i32) -> i64 {
+    -i64::from(a)
+}
+
+// The absolute value without losing the one result that doesn't fit in 16.16, |i32::MIN|.
+pub fn fp_abs_wide(a: i32) -> i64 {
+    i64::from(a).abs()
+}
+
+// The largest integer no greater than a, so fp_floor(-1.25) is -2.
+pub fn fp_floor(a: i32) -> i32 {
+    a & !(FP_ONE - 1)
+}
+
+// The smallest integer no less than a, without losing results above the largest 16.16 value.
+pub fn fp_ceil_wide(a: i32) -> i64 {
+    (i64::from(a) + i64::from(FP_ONE - 1)) & !i64::from(FP_ONE - 1)
+}
+
+// What a is above fp_floor(a), which is always in [0, 1), so fp_frac(-1.25) is 0.75.
+pub fn fp_frac(a: i32) -> i32 {
+    a & (FP_ONE - 1)
+}
+
+// Converts a plain integer to 16.16 without losing a result that doesn't fit.
+pub fn int_to_fix_wide(a: i32) -> i64 {
+    i64::from(a) << 16
+}
+
+// Converts to a plain integer, rounding as the mode asks. The result always fits.
+pub fn fix_to_int(a: i32, mode: Rounding) -> i32 {
+    round_div(i64::from(a), i64::from(FP_ONE), mode) as i32
+}
+
+// Adds the exact 32.32 product of a and b to a 32.32 accumulator. Returns None if the sum doesn't
+// fit in 64 bits and the policy is Trapping.
+pub fn fp_mac(acc: i64, a: i32, b: i32, policy: Overflow) -> Option<i64> {
+    let product = i64::from(a) * i64::from(b);
+    match policy {
+        Overflow::Wrapping => Some(acc.wrapping_add(product)),
+        Overflow::Saturating => Some(acc.saturating_add(product)),
+        Overflow::Trapping => acc.checked_add(product),
+    }
+}
+
+// Rounds a 32.32 accumulator to 16.16 without losing a result that doesn't fit.
+pub fn fp_round_acc_wide(acc: i64, mode: Rounding) -> i64 {
+    round_div(acc, i64::from(FP_ONE), mode)
+}
+
+// Divides n by d, rounding the quotient as the mode asks.
+fn round_div(n: i64, d: i64, mode: Rounding) -> i64 {
+    let q = n / d;
+    let r = n % d;
+    if r == 0 {
+        return q;
+    }
+    // Integer division rounds toward zero, so this steps the quotient away from it.
+    let away = if (n < 0) != (d < 0) { -1 } else { 1 };
+    let twice_r = 2 * r.abs();
+    match mode {
+        Rounding::Floor if away < 0 => q - 1,
+        Rounding::Floor => q,
+        Rounding::Truncate => q,
+        _ if twice_r > d.abs() => q + away,
+        _ if twice_r < d.abs() => q,
+        Rounding::HalfEven if q % 2 == 0 => q,
+        _ => q + away,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    const VAL_A: f32 = 1234.5678;
+    const VAL_A_FP: i32 = (VAL_A * (1 << 16) as f32) as i32;
+    const VAL_B: f32 = -8675.309;
+    const VAL_B_FP: i32 = (VAL_B * (1 << 16) as f32) as i32;
+
+    #[test]
+    fn zero() {
+        assert_eq!(float_to_fix(0.0), 0);
+    }
+
+    #[test]
+    fn one() {
+        assert_eq!(float_to_fix(1.0), FP_ONE);
+    }
+
+    #[test]
+    fn a() {
+        assert_eq!(float_to_fix(VAL_A), VAL_A_FP);
+    }
+
+    #[test]
+    fn b() {
+        assert_eq!(float_to_fix(VAL_B), VAL_B_FP);
+    }
+
+    #[test]
+    fn back_and_forth() {
+        let a_fp = float_to_fix(VAL_A);
+        let b_fp = float_to_fix(VAL_B);
+        assert_eq!(fix_to_float(a_fp), VAL_A);
+        assert_eq!(fix_to_float(b_fp), VAL_B);
+    }
+
+    #[test]
+    fn f64_conversions() {
+        assert_eq!(f64_to_fix(0.0), Some(0));
+        assert_eq!(f64_to_fix(-1.0), Some(-FP_ONE));
+        // f32 can't hold these exactly.
+        assert_eq!(f64_to_fix(30000.0 + 1.0 / 65536.0), Some((30000 << 16) + 1));
+        assert_eq!(fix_to_f64((30000 << 16) + 1), 30000.0 + 1.0 / 65536.0);
+        assert_eq!(f64_to_fix(32767.0 + 65535.0 / 65536.0), Some(i32::MAX));
+        assert_eq!(f64_to_fix(-32768.0), Some(i32::MIN));
+        assert_eq!(f64_to_fix(32768.0), None);
+        assert_eq!(f64_to_fix(-32768.00001), None);
+        assert_eq!(f64_to_fix(f64::NAN), None);
+        assert_eq!(f64_to_fix(f64::INFINITY), None);
+        // Half an LSB rounds to even.
+        let lsb = 1.0 / 65536.0;
+        assert_eq!(f64_to_fix(0.5 * lsb), Some(0));
+        assert_eq!(f64_to_fix(1.5 * lsb), Some(2));
+        assert_eq!(f64_to_fix(-1.5 * lsb), Some(-2));
+        assert_eq!(f64_to_fix(0.6 * lsb), Some(1));
+        for bits in (i32::MIN..=i32::MAX).step_by(0x10001) {
+            assert_eq!(f64_to_fix(fix_to_f64(bits)), Some(bits));
+        }
+    }
+
+    #[test]
+    fn multiply_basic() {
+        let a_fp: i32 = 0;
+        let b_fp: i32 = 0;
+        assert_eq!(fp_mul(a_fp, b_fp), 0);
+        let a_fp: i32 = FP_ONE;
+        let b_fp: i32 = 0;
+        assert_eq!(fp_mul(a_fp, b_fp), 0);
+        let a_fp: i32 = float_to_fix(1.0);
+        let b_fp: i32 = float_to_fix(1.0);
+        assert_eq!(fp_mul(a_fp, b_fp), FP_ONE);
+        let a_fp: i32 = float_to_fix(5.0);
+        let b_fp: i32 = float_to_fix(5.0);
+        assert_eq!(fix_to_float(fp_mul(a_fp, b_fp)), 25.0);
+        let a_fp: i32 = float_to_fix(5.0);
+        let b_fp: i32 = float_to_fix(-5.0);
+        assert_eq!(fix_to_float(fp_mul(a_fp, b_fp)), -25.0);
+    }
+
+    #[test]
+    fn multiply_advanced() {
+        for &(a, b) in [(12.56, 34.23), (12.56, -34.23)].iter() {
+            let a_fp = float_to_fix(a);
+            let b_fp = float_to_fix(b);
+            let exact = fix_to_f64(a_fp) * fix_to_f64(b_fp);
+            assert_floor(fp_mul(a_fp, b_fp), exact);
+            assert_nearest(fp_mul_rounded(a_fp, b_fp, Rounding::HalfEven), exact);
+            assert_nearest(
+                fp_mul_rounded(a_fp, b_fp, Rounding::HalfAwayFromZero),
+                exact,
+            );
+        }
+    }
+
+    #[test]
+    fn divide_basic() {
+        let a_fp: i32 = FP_ONE;
+        let b_fp: i32 = 0;
+        assert_eq!(fp_div(a_fp, b_fp), 0); // The raw helper hides divide by zero.
+        let a_fp: i32 = float_to_fix(1.0);
+        let b_fp: i32 = float_to_fix(1.0);
+        assert_eq!(fp_div(a_fp, b_fp), FP_ONE);
+        let a_fp: i32 = float_to_fix(4.0);
+        let b_fp: i32 = float_to_fix(2.0);
+        assert_eq!(fix_to_float(fp_div(a_fp, b_fp)), 2.0);
+        let a_fp: i32 = float_to_fix(6.0);
+        let b_fp: i32 = float_to_fix(-2.0);
+        assert_eq!(fix_to_float(fp_div(a_fp, b_fp)), -3.0);
+    }
+
+    // Checks a result is within half an LSB of the exact value.
+    fn assert_nearest(result: i32, exact: f64) {
+        let diff = (f64::from(result) - exact * f64::from(FP_ONE)).abs();
+        assert!(diff <= 0.5, "{} is {} LSBs from {}", result, diff, exact);
+    }
+
+    // Checks a result is the exact value rounded down.
+    fn assert_floor(result: i32, exact: f64) {
+        let diff = exact * f64::from(FP_ONE) - f64::from(result);
+        assert!(
+            (0.0..1.0).contains(&diff),
+            "{} is {} LSBs below {}",
+            result,
+            diff,
+            exact
+        );
+    }
+
+    #[test]
+    fn rounded_within_half_lsb() {
+        let vals = [
+            VAL_A_FP, VAL_B_FP, FP_ONE, -FP_ONE, 1, -1, 3, 0x8000, -0x8000, 12345, -98765, 0x7fff,
+        ];
+        for mode in [Rounding::HalfEven, Rounding::HalfAwayFromZero].iter() {
+            for &a in vals.iter() {
+                for &b in vals.iter() {
+                    let exact_mul = f64::from(a) * f64::from(b) / f64::from(FP_ONE).powi(2);
+                    if exact_mul.abs() < f64::from(i32::MAX) / f64::from(FP_ONE) {
+                        assert_nearest(fp_mul_rounded(a, b, *mode), exact_mul);
+                    }
+                    let exact_div = f64::from(a) / f64::from(b);
+                    if exact_div.abs() < f64::from(i32::MAX) / f64::from(FP_ONE) {
+                        assert_nearest(fp_div_rounded(a, b, *mode), exact_div);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn narrowing() {
+        let big = i64::from(i32::MAX) + 1;
+        let small = i64::from(i32::MIN) - 1;
+        assert_eq!(narrow(big, Overflow::Wrapping), Some(i32::MIN));
+        assert_eq!(narrow(small, Overflow::Wrapping), Some(i32::MAX));
+        assert_eq!(narrow(big, Overflow::Saturating), Some(i32::MAX));
+        assert_eq!(narrow(small, Overflow::Saturating), Some(i32::MIN));
+        assert_eq!(narrow(big, Overflow::Trapping), None);
+        assert_eq!(narrow(small, Overflow::Trapping), None);
+        for policy in [Overflow::Wrapping, Overflow::Saturating, Overflow::Trapping].iter() {
+            assert_eq!(narrow(-5, *policy), Some(-5));
+            assert_eq!(narrow(i64::from(i32::MAX), *policy), Some(i32::MAX));
+        }
+        // 200 * 200 and 200 / 0.001 don't fit.
+        let wide = fp_mul_wide(float_to_fix(200.0), float_to_fix(200.0), Rounding::Truncate);
+        assert_eq!(wide, i64::from(float_to_fix(200.0)) * 200);
+        assert_eq!(narrow(wide, Overflow::Trapping), None);
+        let wide = fp_div_wide(float_to_fix(200.0), 65, Rounding::Truncate);
+        assert!(wide > i64::from(i32::MAX));
+    }
+
+    #[test]
+    fn rounding_ties() {
+        let half = 0x8000;
+        // 0.5, 1.5 and -0.5 LSBs.
+        assert_eq!(fp_mul_rounded(1, half, Rounding::Floor), 0);
+        assert_eq!(fp_mul_rounded(1, half, Rounding::Truncate), 0);
+        assert_eq!(fp_mul_rounded(1, half, Rounding::HalfEven), 0);
+        assert_eq!(fp_mul_rounded(1, half, Rounding::HalfAwayFromZero), 1);
+        assert_eq!(fp_mul_rounded(3, half, Rounding::Floor), 1);
+        assert_eq!(fp_mul_rounded(3, half, Rounding::Truncate), 1);
+        assert_eq!(fp_mul_rounded(3, half, Rounding::HalfEven), 2);
+        assert_eq!(fp_mul_rounded(3, half, Rounding::HalfAwayFromZero), 2);
+        assert_eq!(fp_mul_rounded(-1, half, Rounding::Floor), -1);
+        assert_eq!(fp_mul_rounded(-1, half, Rounding::Truncate), 0);
+        assert_eq!(fp_mul_rounded(-1, half, Rounding::HalfEven), 0);
+        assert_eq!(fp_mul_rounded(-1, half, Rounding::HalfAwayFromZero), -1);
+        // 1 / 2 LSBs and -3 / 2 LSBs.
+        let two = float_to_fix(2.0);
+        assert_eq!(fp_div_rounded(1, two, Rounding::Truncate), 0);
+        assert_eq!(fp_div_rounded(1, two, Rounding::HalfEven), 0);
+        assert_eq!(fp_div_rounded(1, two, Rounding::HalfAwayFromZero), 1);
+        assert_eq!(fp_div_rounded(-3, two, Rounding::Floor), -2);
+        assert_eq!(fp_div_rounded(-3, two, Rounding::Truncate), -1);
+        assert_eq!(fp_div_rounded(-3, two, Rounding::HalfEven), -2);
+        assert_eq!(fp_div_rounded(-3, two, Rounding::HalfAwayFromZero), -2);
+        assert_eq!(fp_div_rounded(3, -two, Rounding::HalfEven), -2);
+        assert_eq!(fp_div_rounded(1, -two, Rounding::Floor), -1);
+        assert_eq!(fp_div_rounded(1, -two, Rounding::Truncate), 0);
+        // Flooring matches the plain versions, and a shift.
+        assert_eq!(
+            fp_mul(VAL_A_FP, VAL_B_FP),
+            fp_mul_rounded(VAL_A_FP, VAL_B_FP, Rounding::Floor)
+        );
+        assert_eq!(
+            fp_mul(VAL_A_FP, VAL_B_FP),
+            ((i64::from(VAL_A_FP) * i64::from(VAL_B_FP)) >> 16) as i32
+        );
+        assert_eq!(
+            fp_div(VAL_B_FP, VAL_A_FP),
+            fp_div_rounded(VAL_B_FP, VAL_A_FP, Rounding::Floor)
+        );
+    }
+
+    #[test]
+    fn divide_checked() {
+        assert_eq!(fp_div_checked(FP_ONE, 0), None);
+        assert_eq!(fp_div_checked(0, 0), None);
+        assert_eq!(fp_div_checked(float_to_fix(30000.0), 1), None);
+        assert_eq!(fp_div_checked(i32::MIN, -FP_ONE), None);
+        assert_eq!(
+            fp_div_checked(float_to_fix(6.0), float_to_fix(-2.0)),
+            Some(float_to_fix(-3.0))
+        );
+        assert_eq!(
+            fp_div_checked(VAL_A_FP, VAL_B_FP),
+            Some(fp_div(VAL_A_FP, VAL_B_FP))
+        );
+        assert_eq!(fp_div_zero_saturated(FP_ONE), i32::MAX);
+        assert_eq!(fp_div_zero_saturated(-1), i32::MIN);
+        assert_eq!(fp_div_zero_saturated(0), 0);
+    }
+
+    #[test]
+    fn modulo() {
+        let fix = float_to_fix;
+        assert_eq!(fp_mod(fix(7.5), fix(2.0)), Some(fix(1.5)));
+        assert_eq!(fp_mod(fix(-7.5), fix(2.0)), Some(fix(-1.5)));
+        assert_eq!(fp_mod(fix(7.5), fix(-2.0)), Some(fix(1.5)));
+        assert_eq!(fp_mod(fix(1.0), fix(0.375)), Some(fix(0.25)));
+        assert_eq!(fp_mod(i32::MIN, -1), Some(0));
+        assert_eq!(fp_mod(i32::MIN, -FP_ONE), Some(0));
+        assert_eq!(fp_mod(FP_ONE, 0), None);
+    }
+
+    #[test]
+    fn sign_and_integer_parts() {
+        let fix = float_to_fix;
+        assert_eq!(fp_neg_wide(fix(1.5)), i64::from(fix(-1.5)));
+        assert_eq!(fp_neg_wide(i32::MIN), 1 << 31);
+        assert_eq!(fp_abs_wide(fix(-1.5)), i64::from(fix(1.5)));
+        assert_eq!(fp_abs_wide(i32::MIN), 1 << 31);
+        let cases = [
+            (1.25, 1.0, 2.0, 0.25),
+            (-1.25, -2.0, -1.0, 0.75),
+            (3.0, 3.0, 3.0, 0.0),
+            (-3.0, -3.0, -3.0, 0.0),
+            (-0.5, -1.0, 0.0, 0.5),
+        ];
+        for (a, floor, ceil, frac) in cases.iter() {
+            assert_eq!(fp_floor(fix(*a)), fix(*floor), "floor({})", a);
+            assert_eq!(fp_ceil_wide(fix(*a)), i64::from(fix(*ceil)), "ceil({})", a);
+            assert_eq!(fp_frac(fix(*a)), fix(*frac), "frac({})", a);
+        }
+        assert_eq!(fp_floor(i32::MIN), i32::MIN);
+        assert_eq!(fp_frac(i32::MIN), 0);
+        assert_eq!(fp_floor(i32::MAX), 32767 << 16);
+        assert_eq!(fp_frac(i32::MAX), FP_ONE - 1);
+        assert_eq!(fp_ceil_wide(i32::MAX), 32768 << 16);
+        assert_eq!(fp_ceil_wide(i32::MIN), i64::from(i32::MIN));
+    }
+
+    #[test]
+    fn integer_conversions() {
+        assert_eq!(int_to_fix_wide(3), i64::from(float_to_fix(3.0)));
+        assert_eq!(int_to_fix_wide(-32768), i64::from(i32::MIN));
+        assert_eq!(int_to_fix_wide(32768), 1 << 31);
+        let cases = [
+            (2.5, 2, 3),
+            (-2.5, -2, -3),
+            (2.49, 2, 2),
+            (-0.75, 0, -1),
+            (7.0, 7, 7),
+        ];
+        for (a, truncated, rounded) in cases.iter() {
+            let a_fp = float_to_fix(*a);
+            assert_eq!(fix_to_int(a_fp, Rounding::Truncate), *truncated, "{}", a);
+            assert_eq!(
+                fix_to_int(a_fp, Rounding::HalfAwayFromZero),
+                *rounded,
+                "{}",
+                a
+            );
+        }
+        assert_eq!(fix_to_int(i32::MIN, Rounding::Truncate), -32768);
+        assert_eq!(fix_to_int(i32::MAX, Rounding::Truncate), 32767);
+        assert_eq!(fix_to_int(i32::MAX, Rounding::HalfAwayFromZero), 32768);
+    }
+
+    #[test]
+    fn accumulate() {
+        // Products far below an LSB still add up.
+        let small = float_to_fix(0.001);
+        let mut acc = 0;
+        for _ in 0..100 {
+            acc = fp_mac(acc, small, small, Overflow::Trapping).unwrap();
+            assert_eq!(fp_mul(small, small), 0);
+        }
+        assert_eq!(acc, 100 * i64::from(small) * i64::from(small));
+        assert_eq!(fp_round_acc_wide(acc, Rounding::Truncate), 6);
+        assert_eq!(fp_round_acc_wide(acc, Rounding::HalfEven), 6);
+        assert_eq!(fp_round_acc_wide(-acc, Rounding::Truncate), -6);
+        // A single product rounds like a multiply.
+        let acc = fp_mac(0, VAL_A_FP, VAL_B_FP, Overflow::Wrapping).unwrap();
+        for mode in [Rounding::Truncate, Rounding::HalfEven].iter() {
+            assert_eq!(
+                fp_round_acc_wide(acc, *mode),
+                fp_mul_wide(VAL_A_FP, VAL_B_FP, *mode)
+            );
+        }
+        // Intermediate sums may leave 16.16 as long as the result comes back.
+        let big = float_to_fix(200.0);
+        let acc = fp_mac(0, big, big, Overflow::Trapping).unwrap();
+        let acc = fp_mac(acc, big, -big, Overflow::Trapping).unwrap();
+        assert_eq!(acc, 0);
+        let full = fp_mac(i64::MAX, 1, 1, Overflow::Trapping);
+        assert_eq!(full, None);
+        assert_eq!(fp_mac(i64::MAX, 1, 1, Overflow::Saturating), Some(i64::MAX));
+        assert_eq!(fp_mac(i64::MAX, 1, 1, Overflow::Wrapping), Some(i64::MIN));
+    }
+
+    #[test]
+    fn divide_advanced() {
+        for &(a, b) in [(31.23, 12.56), (-31.23, 12.56)].iter() {
+            let a_fp = float_to_fix(a);
+            let b_fp = float_to_fix(b);
+            let exact = fix_to_f64(a_fp) / fix_to_f64(b_fp);
+            assert_floor(fp_div(a_fp, b_fp), exact);
+            assert_nearest(fp_div_rounded(a_fp, b_fp, Rounding::HalfEven), exact);
+            assert_nearest(
+                fp_div_rounded(a_fp, b_fp, Rounding::HalfAwayFromZero),
+                exact,
+            );
+        }
+    }
+}
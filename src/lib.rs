@@ -8,6 +8,12 @@ mod fp;
 mod stk;
 mod vm;
 
+pub use fp::{DivZero, Fix16, Overflow, ParseFix16Error, Rounding};
+pub use stk::Stack;
+pub use vm::{
+    MmioDevice, RegionKind, RunResult, StepOutcome, StopReason, Vm, VmConfig, VmError, VmState,
+};
+
 #[cfg(test)]
 mod test {
     #[test]
@@ -1,73 +1,80 @@
-/// Implementation of the different stacks used by our vm.
+//! Implementation of the different stacks used by our vm.
 
-const MAX_STACK_SIZE: usize = 1 << 16;
-const STACK_EMPTY: usize = MAX_STACK_SIZE + 666;
+pub const MAX_STACK_SIZE: usize = 1 << 16;
 
+// Storage is allocated as values are pushed, so empty stacks cost next to nothing.
+#[derive(Clone)]
 pub struct Stack {
-    vals: [i32; MAX_STACK_SIZE],
-    top: usize,
+    vals: Vec<i32>,
+    // Number of values the stack can hold, at most MAX_STACK_SIZE.
+    depth: usize,
 }
 
-impl Copy for Stack {}
-impl Clone for Stack {
-    fn clone(&self) -> Stack {
-        Stack {
-            vals: self.vals,
-            top: self.top,
-        }
+impl Default for Stack {
+    fn default() -> Stack {
+        Stack::new()
     }
 }
 
 impl Stack {
     pub fn new() -> Stack {
+        Stack::with_depth(MAX_STACK_SIZE)
+    }
+
+    pub fn with_depth(depth: usize) -> Stack {
         Stack {
-            vals: [0; MAX_STACK_SIZE],
-            top: STACK_EMPTY,
+            vals: Vec::new(),
+            depth: depth.clamp(1, MAX_STACK_SIZE),
         }
     }
 
+    // Empties the stack, keeping its storage for reuse.
     pub fn clear(&mut self) {
-        self.vals = [0; MAX_STACK_SIZE];
-        self.top = STACK_EMPTY;
+        self.vals.clear();
     }
 
     pub fn empty(&self) -> bool {
-        self.top == STACK_EMPTY
+        self.vals.is_empty()
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.vals.len()
     }
 
     pub fn full(&self) -> bool {
-        self.top == (MAX_STACK_SIZE - 1)
+        self.vals.len() == self.depth
     }
 
     pub fn push(&mut self, a: i32) -> bool {
-        if self.empty() {
-            self.top = 0;
-        } else if self.full() {
+        if self.full() {
             return false;
-        } else {
-            self.top += 1;
         }
-        self.vals[self.top] = a;
+        self.vals.push(a);
         true
     }
 
-    pub fn peek(&mut self) -> Option<i32> {
-        if self.empty() {
-            return None;
-        }
-        Some(self.vals[self.top])
+    pub fn peek(&self) -> Option<i32> {
+        self.vals.last().copied()
+    }
+
+    // The value i places below the top, so peek_n(0) is the same as peek.
+    pub fn peek_n(&self, i: usize) -> Option<i32> {
+        self.vals.iter().rev().nth(i).copied()
+    }
+
+    // Iterates over the values from the top of the stack to the bottom.
+    pub fn iter(&self) -> impl Iterator<Item = i32> + '_ {
+        self.vals.iter().rev().copied()
+    }
+
+    // The values from the bottom of the stack to the top.
+    pub fn as_slice(&self) -> &[i32] {
+        &self.vals
     }
 
     pub fn pop(&mut self) -> Option<i32> {
-        let val = self.peek();
-        if val.is_some() {
-            if self.top == 0 {
-                self.top = STACK_EMPTY;
-            } else {
-                self.top -= 1;
-            }
-        }
-        val
+        self.vals.pop()
     }
 }
 
@@ -78,13 +85,17 @@ mod test {
     #[test]
     fn test_clear() {
         let mut stk = Stack::new();
-        assert_eq!(stk.vals, [0; MAX_STACK_SIZE]);
-        assert_eq!(stk.top, STACK_EMPTY);
-        stk.vals[0] = 666;
-        stk.top = 666;
+        assert!(stk.vals.is_empty());
+        assert_eq!(stk.vals.capacity(), 0, "New stack allocated storage!");
+        for i in 0..666 {
+            stk.push(i);
+        }
+        let capacity = stk.vals.capacity();
         stk.clear();
-        assert_eq!(stk.vals, [0; MAX_STACK_SIZE]);
-        assert_eq!(stk.top, STACK_EMPTY);
+        assert!(stk.empty());
+        assert_eq!(stk.len(), 0);
+        assert_eq!(stk.vals.capacity(), capacity, "Clear released storage!");
+        assert_eq!(stk.pop(), None);
     }
 
     #[test]
@@ -99,32 +110,32 @@ mod test {
         assert!(stk.push(3));
         assert!(!stk.empty());
         let top = stk.peek();
-        assert!(!top.is_none());
+        assert!(top.is_some());
         assert_eq!(top.unwrap(), 3);
 
         assert!(stk.push(4));
         assert!(!stk.empty());
         let top = stk.peek();
-        assert!(!top.is_none());
+        assert!(top.is_some());
         assert_eq!(top.unwrap(), 4);
 
         assert!(stk.push(5));
         assert!(!stk.empty());
         let top = stk.peek();
-        assert!(!top.is_none());
+        assert!(top.is_some());
         assert_eq!(top.unwrap(), 5);
 
         // Pop tests
         let pop_val = stk.pop();
-        assert!(!pop_val.is_none());
+        assert!(pop_val.is_some());
         assert_eq!(pop_val.unwrap(), 5);
 
         let pop_val = stk.pop();
-        assert!(!pop_val.is_none());
+        assert!(pop_val.is_some());
         assert_eq!(pop_val.unwrap(), 4);
 
         let pop_val = stk.pop();
-        assert!(!pop_val.is_none());
+        assert!(pop_val.is_some());
         assert_eq!(pop_val.unwrap(), 3);
 
         let pop_val = stk.pop();
@@ -135,6 +146,39 @@ mod test {
         while !stk.full() {
             stk.push(666);
         }
+        assert_eq!(stk.len(), MAX_STACK_SIZE);
         assert!(!stk.push(666));
     }
+
+    #[test]
+    fn test_introspection() {
+        let mut stk = Stack::new();
+        assert_eq!(stk.peek_n(0), None);
+        assert_eq!(stk.iter().next(), None);
+        assert!(stk.as_slice().is_empty());
+        for i in 1..=3 {
+            stk.push(i);
+        }
+        assert_eq!(stk.len(), 3);
+        assert_eq!(stk.peek_n(0), stk.peek());
+        assert_eq!(stk.peek_n(1), Some(2));
+        assert_eq!(stk.peek_n(2), Some(1));
+        assert_eq!(stk.peek_n(3), None);
+        assert_eq!(stk.iter().collect::<Vec<i32>>(), vec![3, 2, 1]);
+        assert_eq!(stk.as_slice(), &[1, 2, 3]);
+        assert_eq!(stk.len(), 3, "Introspection modified the stack!");
+    }
+
+    #[test]
+    fn test_depth() {
+        let mut stk = Stack::with_depth(2);
+        assert!(stk.push(1));
+        assert!(stk.push(2));
+        assert!(stk.full());
+        assert!(!stk.push(3));
+        assert_eq!(stk.pop(), Some(2));
+        assert!(!stk.full());
+        assert_eq!(Stack::with_depth(0).depth, 1);
+        assert_eq!(Stack::with_depth(MAX_STACK_SIZE + 1).depth, MAX_STACK_SIZE);
+    }
 }
@@ -0,0 +1,300 @@
+use super::opcodes::*;
+use super::VmError;
+use super::{pop, require};
+use crate::fp;
+
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
+    let op_type = AccumulatorOpTypes::from(inst);
+    match op_type {
+        AccumulatorOpTypes::Clear => vm.accumulator = 0,
+        AccumulatorOpTypes::Mac => {
+            require(&vm.data_stack, 2)?;
+            let a = pop(&mut vm.data_stack)?;
+            let b = pop(&mut vm.data_stack)?;
+            match fp::fp_mac(vm.accumulator, a, b, vm.config.overflow) {
+                Some(acc) => vm.accumulator = acc,
+                None => {
+                    vm.data_stack.push(b);
+                    vm.data_stack.push(a);
+                    return Err(VmError::Overflow);
+                }
+            }
+        }
+        AccumulatorOpTypes::Round => {
+            let wide = fp::fp_round_acc_wide(vm.accumulator, vm.config.rounding);
+            let val = fp::narrow(wide, vm.config.overflow).ok_or(VmError::Overflow)?;
+            super::push(&mut vm.data_stack, val)?;
+        }
+        AccumulatorOpTypes::Push => {
+            // The low word goes first, so the high word ends up on top.
+            super::push(&mut vm.data_stack, vm.accumulator as i32)?;
+            if let Err(e) = super::push(&mut vm.data_stack, (vm.accumulator >> 32) as i32) {
+                vm.data_stack.pop();
+                return Err(e);
+            }
+        }
+        AccumulatorOpTypes::Pop => {
+            require(&vm.data_stack, 2)?;
+            let hi = pop(&mut vm.data_stack)?;
+            let lo = pop(&mut vm.data_stack)?;
+            vm.accumulator = (i64::from(hi) << 32) | i64::from(lo as u32);
+        }
+        _ => return Err(super::invalid_opcode(vm, inst)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fp::Fix16;
+    use crate::vm::StopReason;
+    use crate::vm::Vm;
+    use crate::vm::VmConfig;
+
+    // Runs a multiply-accumulate over the pairs and then rounds the result.
+    fn run_test(config: VmConfig, pairs: &[(i32, i32)]) -> Result<i32, VmError> {
+        let mut vm = Vm::with_config(config).unwrap();
+        let mut code = vec![OpCodes::Ext as u8, ExtOpCodes::AClr as u8];
+        for (a, b) in pairs.iter() {
+            vm.data_stack.push(*b);
+            vm.data_stack.push(*a);
+            code.push(OpCodes::Ext as u8);
+            code.push(ExtOpCodes::Mac as u8);
+        }
+        code.push(OpCodes::Ext as u8);
+        code.push(ExtOpCodes::ARnd as u8);
+        assert!(vm.load(&code));
+        for _ in 0..pairs.len() + 2 {
+            vm.cycle_once()?;
+        }
+        assert_eq!(vm.pc, code.len(), "Failed to increment program counter!");
+        let r = vm.data_stack.pop().unwrap();
+        assert!(vm.data_stack.empty(), "Operands left on the stack!");
+        Ok(r)
+    }
+
+    #[test]
+    fn test_mac() {
+        let fix = fp::float_to_fix;
+        let config = VmConfig::new;
+        assert_eq!(
+            run_test(config(), &[(fix(1.5), fix(2.0)), (fix(-0.25), fix(4.0))]),
+            Ok(fix(2.0))
+        );
+        // Products below an LSB, which Mul would drop, still add up.
+        let small = fix(0.001);
+        let pairs = vec![(small, small); 100];
+        assert_eq!(run_test(config(), &pairs), Ok(6));
+        // Intermediate sums may leave the range of 16.16.
+        let big = fix(200.0);
+        let pairs = [
+            (big, big),
+            (big, big),
+            (-big, big),
+            (-big, big),
+            (fix(0.5), 1),
+        ];
+        assert_eq!(run_test(config(), &pairs), Ok(0));
+        // The rounding mode applies, as for Mul. These sum to 2.5 LSBs.
+        let pairs = [(fix(1.5), 1), (fix(1.0), 1)];
+        let rounded = |mode| run_test(VmConfig::new().rounding(mode), &pairs);
+        assert_eq!(rounded(fp::Rounding::Truncate), Ok(2));
+        assert_eq!(rounded(fp::Rounding::HalfEven), Ok(2));
+        assert_eq!(rounded(fp::Rounding::HalfAwayFromZero), Ok(3));
+    }
+
+    #[test]
+    fn test_clear_and_round() {
+        let mut vm = Vm::new();
+        vm.accumulator = 5 << 32;
+        let code = [
+            OpCodes::Ext as u8,
+            ExtOpCodes::ARnd as u8,
+            OpCodes::Ext as u8,
+            ExtOpCodes::AClr as u8,
+            OpCodes::Ext as u8,
+            ExtOpCodes::ARnd as u8,
+        ];
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        vm.cycle_once().unwrap();
+        vm.cycle_once().unwrap();
+        // Rounding leaves the accumulator alone.
+        assert_eq!(vm.data_stack.as_slice(), &[5 << 16, 0]);
+        assert_eq!(vm.accumulator(), 0);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let big = fp::float_to_fix(200.0);
+        // 40000 doesn't fit in 16.16.
+        let result = |policy| {
+            let config = VmConfig::new().overflow(policy);
+            run_test(config, &[(big, big)])
+        };
+        assert_eq!(result(fp::Overflow::Saturating), Ok(i32::MAX));
+        assert_eq!(result(fp::Overflow::Trapping), Err(VmError::Overflow));
+        // Overflowing the accumulator itself leaves it and the operands alone.
+        let config = VmConfig::new().overflow(fp::Overflow::Trapping);
+        let mut vm = Vm::with_config(config).unwrap();
+        vm.accumulator = i64::MAX;
+        vm.data_stack.push(1);
+        vm.data_stack.push(2);
+        assert!(vm.load(&[OpCodes::Ext as u8, ExtOpCodes::Mac as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::Overflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.as_slice(), &[1, 2], "Operands not restored!");
+        assert_eq!(vm.accumulator(), i64::MAX);
+    }
+
+    #[test]
+    fn test_underflow() {
+        let mut vm = Vm::new();
+        vm.data_stack.push(1);
+        assert!(vm.load(&[OpCodes::Ext as u8, ExtOpCodes::Mac as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.data_stack.as_slice(), &[1], "Operand consumed!");
+        assert_eq!(vm.accumulator(), 0);
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut vm = Vm::new();
+        vm.accumulator = -(3 << 32) - 1;
+        let code = [
+            OpCodes::Ext as u8,
+            ExtOpCodes::APush as u8,
+            OpCodes::Ext as u8,
+            ExtOpCodes::AClr as u8,
+            OpCodes::Ext as u8,
+            ExtOpCodes::APop as u8,
+        ];
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 2, "APush failed to increment program counter!");
+        assert_eq!(
+            vm.data_stack.as_slice(),
+            &[-1, -4],
+            "APush pushed the wrong words!"
+        );
+        assert_eq!(
+            vm.accumulator(),
+            -(3 << 32) - 1,
+            "APush modified the accumulator!"
+        );
+        vm.cycle_once().unwrap();
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 6, "APop failed to increment program counter!");
+        assert!(vm.data_stack.empty(), "APop left data on the stack!");
+        assert_eq!(
+            vm.accumulator(),
+            -(3 << 32) - 1,
+            "APop restored the wrong value!"
+        );
+    }
+
+    #[test]
+    fn test_push_and_pop_faults() {
+        // APush needs room for both words.
+        let mut vm = Vm::with_config(VmConfig::new().stack_depth(2)).unwrap();
+        vm.accumulator = 5 << 32;
+        vm.data_stack.push(7);
+        assert!(vm.load(&[OpCodes::Ext as u8, ExtOpCodes::APush as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackOverflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.as_slice(), &[7], "APush left half a value!");
+        // APop needs both words.
+        let mut vm = Vm::new();
+        vm.accumulator = 5 << 32;
+        vm.data_stack.push(7);
+        assert!(vm.load(&[OpCodes::Ext as u8, ExtOpCodes::APop as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.data_stack.as_slice(), &[7], "Operand consumed!");
+        assert_eq!(vm.accumulator(), 5 << 32);
+    }
+
+    #[test]
+    fn test_interrupt_saves_accumulator() {
+        // Sums 3 * 0.5 + 2 * 0.5 + 1 * 0.5 in the accumulator while a handler uses it too.
+        //  0: AClr
+        //  2: PushImm 3.0
+        //  7: Dup          <- loop
+        //  8: PushImm 0.5
+        // 13: Mac
+        // 15: PushImm 1.0
+        // 20: Sub
+        // 21: Dup
+        // 22: PushImm 0.0
+        // 27: BgtImm 7.0
+        // 32: ARnd
+        // 34: Brk
+        let fix = fp::float_to_fix;
+        let mut code = vec![0u8; 0x120];
+        code[0] = OpCodes::Ext as u8;
+        code[1] = ExtOpCodes::AClr as u8;
+        code[2] = OpCodes::PushImm as u8;
+        code[3..7].clone_from_slice(&fix(3.0).to_le_bytes());
+        code[7] = OpCodes::Dup as u8;
+        code[8] = OpCodes::PushImm as u8;
+        code[9..13].clone_from_slice(&fix(0.5).to_le_bytes());
+        code[13] = OpCodes::Ext as u8;
+        code[14] = ExtOpCodes::Mac as u8;
+        code[15] = OpCodes::PushImm as u8;
+        code[16..20].clone_from_slice(&fix(1.0).to_le_bytes());
+        code[20] = OpCodes::Sub as u8;
+        code[21] = OpCodes::Dup as u8;
+        code[22] = OpCodes::PushImm as u8;
+        code[23..27].clone_from_slice(&fix(0.0).to_le_bytes());
+        code[27] = OpCodes::BgtImm as u8;
+        code[28..32].clone_from_slice(&fix(7.0).to_le_bytes());
+        code[32] = OpCodes::Ext as u8;
+        code[33] = ExtOpCodes::ARnd as u8;
+        code[34] = OpCodes::Brk as u8;
+        // The handler computes 4 * 0.25 on port 2, saving and restoring the accumulator.
+        let handler = [
+            OpCodes::Ext as u8,
+            ExtOpCodes::APush as u8,
+            OpCodes::Ext as u8,
+            ExtOpCodes::AClr as u8,
+            OpCodes::PushImm as u8,
+        ];
+        code[0x100..0x105].clone_from_slice(&handler);
+        code[0x105..0x109].clone_from_slice(&fix(4.0).to_le_bytes());
+        code[0x109] = OpCodes::PushImm as u8;
+        code[0x10a..0x10e].clone_from_slice(&fix(0.25).to_le_bytes());
+        let handler = [
+            OpCodes::Ext as u8,
+            ExtOpCodes::Mac as u8,
+            OpCodes::Ext as u8,
+            ExtOpCodes::ARnd as u8,
+            OpCodes::PortPush as u8 | 2,
+            OpCodes::Ext as u8,
+            ExtOpCodes::APop as u8,
+            OpCodes::Reti as u8,
+        ];
+        code[0x10e..0x116].clone_from_slice(&handler);
+        let mut vm = Vm::new();
+        assert!(vm.load(&code));
+        vm.interrupts[1] = 0x100;
+        // Interrupt the loop right after its first Mac.
+        while vm.pc != 15 {
+            vm.cycle_once().unwrap();
+        }
+        assert_eq!(vm.accumulator(), 3 << 31);
+        assert!(vm.port_push(1, Fix16::ONE));
+        let result = vm.run(100);
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(
+            vm.port_pop(2),
+            Some(Fix16::ONE),
+            "Handler got the wrong result!"
+        );
+        assert_eq!(
+            vm.data_stack.as_slice(),
+            &[0, fix(3.0)],
+            "Loop got the wrong result!"
+        );
+        assert_eq!(vm.accumulator(), 3 << 32);
+    }
+}
@@ -1,55 +1,196 @@
 use super::opcodes::*;
+use super::VmError;
+use super::{pop, require};
 use crate::fp;
 use crate::stk::Stack;
 
-pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) {
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
     let op_type = ArithmeticOpTypes::from(inst);
     match op_type {
         ArithmeticOpTypes::Add => {
-            if let Some(a) = vm.data_stack.pop() {
-                if let Some(b) = vm.data_stack.pop() {
-                    vm.data_stack.push(a + b);
-                } else {
-                    vm.data_stack.push(a);
-                }
-            }
+            let (a, b) = pop_operands(vm)?;
+            push_result(vm, a, b, i64::from(a) + i64::from(b))
         }
         ArithmeticOpTypes::Sub => {
-            if let Some(a) = vm.data_stack.pop() {
-                if let Some(b) = vm.data_stack.pop() {
-                    vm.data_stack.push(b - a);
-                } else {
-                    vm.data_stack.push(a);
-                }
-            }
+            let (a, b) = pop_operands(vm)?;
+            push_result(vm, a, b, i64::from(b) - i64::from(a))
         }
         ArithmeticOpTypes::Mul => {
-            if let Some(a) = vm.data_stack.pop() {
-                if let Some(b) = vm.data_stack.pop() {
-                    vm.data_stack.push(fp::fp_mul(a, b));
-                } else {
-                    vm.data_stack.push(a);
+            let (a, b) = pop_operands(vm)?;
+            push_result(vm, a, b, fp::fp_mul_wide(a, b, vm.config.rounding))
+        }
+        ArithmeticOpTypes::Div => {
+            let (a, b) = pop_operands(vm)?;
+            if b == 0 {
+                return div_by_zero(vm, a, b);
+            }
+            push_result(vm, a, b, fp::fp_div_wide(a, b, vm.config.rounding))
+        }
+        _ => Err(super::invalid_opcode(vm, inst)),
+    }
+}
+
+// Extended arithmetic ops, decoded from the byte after the Ext prefix.
+pub(super) fn cycle_ext_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
+    let op_type = ExtArithmeticOpTypes::from(inst);
+    match op_type {
+        ExtArithmeticOpTypes::Mod => {
+            // The remainder of the division Div does on the same operands.
+            let (a, b) = pop_operands(vm)?;
+            match fp::fp_mod(a, b) {
+                Some(val) => {
+                    vm.data_stack.push(val);
+                    Ok(())
                 }
+                None => div_by_zero(vm, a, b),
             }
         }
-        ArithmeticOpTypes::Div => {
-            if let Some(a) = vm.data_stack.pop() {
-                if let Some(b) = vm.data_stack.pop() {
-                    vm.data_stack.push(fp::fp_div(a, b));
-                } else {
-                    vm.data_stack.push(a);
+        ExtArithmeticOpTypes::Neg => {
+            let a = pop(&mut vm.data_stack)?;
+            push_unary_result(vm, a, fp::fp_neg_wide(a))
+        }
+        ExtArithmeticOpTypes::Abs => {
+            let a = pop(&mut vm.data_stack)?;
+            push_unary_result(vm, a, fp::fp_abs_wide(a))
+        }
+        ExtArithmeticOpTypes::Min => {
+            let (a, b) = pop_operands(vm)?;
+            vm.data_stack.push(a.min(b));
+            Ok(())
+        }
+        ExtArithmeticOpTypes::Max => {
+            let (a, b) = pop_operands(vm)?;
+            vm.data_stack.push(a.max(b));
+            Ok(())
+        }
+        ExtArithmeticOpTypes::Floor => {
+            let a = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(fp::fp_floor(a));
+            Ok(())
+        }
+        ExtArithmeticOpTypes::Ceil => {
+            let a = pop(&mut vm.data_stack)?;
+            push_unary_result(vm, a, fp::fp_ceil_wide(a))
+        }
+        ExtArithmeticOpTypes::Frac => {
+            let a = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(fp::fp_frac(a));
+            Ok(())
+        }
+        _ => Err(super::invalid_opcode(vm, inst)),
+    }
+}
+
+// Integer ops, decoded from the byte after the Ext prefix. These treat values as plain integers
+// rather than 16.16.
+pub(super) fn cycle_int_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
+    let op_type = IntegerOpTypes::from(inst);
+    match op_type {
+        IntegerOpTypes::Mul => {
+            let (a, b) = pop_operands(vm)?;
+            push_result(vm, a, b, i64::from(a) * i64::from(b))
+        }
+        IntegerOpTypes::Div => {
+            let (a, b) = pop_operands(vm)?;
+            if b == 0 {
+                return div_by_zero(vm, a, b);
+            }
+            // Integer division always truncates, whatever the rounding mode.
+            push_result(vm, a, b, i64::from(a) / i64::from(b))
+        }
+        IntegerOpTypes::Add => {
+            let (a, b) = pop_operands(vm)?;
+            push_result(vm, a, b, i64::from(a) + i64::from(b))
+        }
+        IntegerOpTypes::Mod => {
+            let (a, b) = pop_operands(vm)?;
+            match fp::fp_mod(a, b) {
+                Some(val) => {
+                    vm.data_stack.push(val);
+                    Ok(())
                 }
+                None => div_by_zero(vm, a, b),
             }
         }
-        _ => {}
+        IntegerOpTypes::FromInt => {
+            let a = pop(&mut vm.data_stack)?;
+            push_unary_result(vm, a, fp::int_to_fix_wide(a))
+        }
+        IntegerOpTypes::ToInt => {
+            let a = pop(&mut vm.data_stack)?;
+            vm.data_stack
+                .push(fp::fix_to_int(a, fp::Rounding::Truncate));
+            Ok(())
+        }
+        IntegerOpTypes::ToIntRounded => {
+            let a = pop(&mut vm.data_stack)?;
+            let rounding = fp::Rounding::HalfAwayFromZero;
+            vm.data_stack.push(fp::fix_to_int(a, rounding));
+            Ok(())
+        }
+        _ => Err(super::invalid_opcode(vm, inst)),
+    }
+}
+
+// Handles a zero divisor for DIV, MOD, IDIV and IMOD, which all saturate the same way.
+fn div_by_zero(vm: &mut super::Vm, a: i32, b: i32) -> Result<(), VmError> {
+    if let fp::DivZero::Saturate = vm.config.div_zero {
+        vm.data_stack.push(fp::fp_div_zero_saturated(a));
+        return Ok(());
+    }
+    restore_operands(vm, a, b);
+    Err(VmError::DivideByZero)
+}
+
+// Like push_result, for unary ops.
+fn push_unary_result(vm: &mut super::Vm, a: i32, result: i64) -> Result<(), VmError> {
+    match fp::narrow(result, vm.config.overflow) {
+        Some(val) => {
+            vm.data_stack.push(val);
+            Ok(())
+        }
+        None => {
+            vm.data_stack.push(a);
+            Err(VmError::Overflow)
+        }
     }
 }
 
+// Pushes the result of a binary op after applying the overflow policy. If the policy traps, the
+// operands are put back instead.
+fn push_result(vm: &mut super::Vm, a: i32, b: i32, result: i64) -> Result<(), VmError> {
+    match fp::narrow(result, vm.config.overflow) {
+        Some(val) => {
+            vm.data_stack.push(val);
+            Ok(())
+        }
+        None => {
+            restore_operands(vm, a, b);
+            Err(VmError::Overflow)
+        }
+    }
+}
+
+// Puts back the operands taken by pop_operands.
+fn restore_operands(vm: &mut super::Vm, a: i32, b: i32) {
+    vm.data_stack.push(b);
+    vm.data_stack.push(a);
+}
+
+// Pops the two operands of a binary op, top of the stack first.
+fn pop_operands(vm: &mut super::Vm) -> Result<(i32, i32), VmError> {
+    require(&vm.data_stack, 2)?;
+    let a = pop(&mut vm.data_stack)?;
+    let b = pop(&mut vm.data_stack)?;
+    Ok((a, b))
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
     use crate::vm::Vm;
+    use crate::vm::VmConfig;
     use crate::vm::INVALID_INTERRUPT;
     use crate::vm::RAM_SIZE;
 
@@ -69,7 +210,7 @@ mod test {
 
     #[test]
     fn test_add_op() {
-        fn run_test(a: f32, b: f32) -> () {
+        fn run_test(a: f32, b: f32) {
             let mut vm = init_vm();
             let c = a + b;
             let a_fp = fp::float_to_fix(a);
@@ -80,10 +221,10 @@ mod test {
             let mut code = [0u8; RAM_SIZE];
             code[0] = OpCodes::Add as u8;
             vm.load(&code);
-            vm.cycle_once();
+            vm.cycle_once().unwrap();
             assert_eq!(vm.pc, 1, "Failed to increment program counter.");
             let r = vm.data_stack.pop();
-            assert!(!r.is_none(), "Data stack empty after add.");
+            assert!(r.is_some(), "Data stack empty after add.");
             assert_eq!(r.unwrap(), c_fp, "Wrong value after add.");
             assert!(
                 vm.data_stack.pop().is_none(),
@@ -98,7 +239,7 @@ mod test {
 
     #[test]
     fn test_sub_op() {
-        fn run_test(a: f32, b: f32) -> () {
+        fn run_test(a: f32, b: f32) {
             let mut vm = init_vm();
             let c = a - b;
             let a_fp = fp::float_to_fix(a);
@@ -109,10 +250,10 @@ mod test {
             let mut code = [0u8; RAM_SIZE];
             code[0] = OpCodes::Sub as u8;
             vm.load(&code);
-            vm.cycle_once();
+            vm.cycle_once().unwrap();
             assert_eq!(vm.pc, 1, "Failed to increment program counter.");
             let r = vm.data_stack.pop();
-            assert!(!r.is_none(), "Data stack empty after sub.");
+            assert!(r.is_some(), "Data stack empty after sub.");
             assert_eq!(r.unwrap(), c_fp, "Wrong value after sub.");
             assert!(
                 vm.data_stack.pop().is_none(),
@@ -127,7 +268,7 @@ mod test {
 
     #[test]
     fn test_mul_op() {
-        fn run_test(a: f32, b: f32) -> () {
+        fn run_test(a: f32, b: f32) {
             let mut vm = init_vm();
             let c = a * b;
             let a_fp = fp::float_to_fix(a);
@@ -138,10 +279,10 @@ mod test {
             let mut code = [0u8; RAM_SIZE];
             code[0] = OpCodes::Mul as u8;
             vm.load(&code);
-            vm.cycle_once();
+            vm.cycle_once().unwrap();
             assert_eq!(vm.pc, 1, "Failed to increment program counter.");
             let r = vm.data_stack.pop();
-            assert!(!r.is_none(), "Data stack empty after mul.");
+            assert!(r.is_some(), "Data stack empty after mul.");
             assert_eq!(r.unwrap(), c_fp, "Wrong value after mul.");
             assert!(
                 vm.data_stack.pop().is_none(),
@@ -157,21 +298,22 @@ mod test {
 
     #[test]
     fn test_div_op() {
-        fn run_test(a: f32, b: f32) -> () {
+        fn run_test(a: f32, b: f32) {
             let mut vm = init_vm();
             let c = b / a;
             let a_fp = fp::float_to_fix(a);
             let b_fp = fp::float_to_fix(b);
-            let c_fp = fp::float_to_fix(c);
+            // Div rounds down by default.
+            let c_fp = (f64::from(c) * f64::from(1 << 16)).floor() as i32;
             vm.data_stack.push(a_fp);
             vm.data_stack.push(b_fp);
             let mut code = [0u8; RAM_SIZE];
             code[0] = OpCodes::Div as u8;
             vm.load(&code);
-            vm.cycle_once();
+            vm.cycle_once().unwrap();
             assert_eq!(vm.pc, 1, "Failed to increment program counter.");
             let r = vm.data_stack.pop();
-            assert!(!r.is_none(), "Data stack empty after div.");
+            assert!(r.is_some(), "Data stack empty after div.");
             let _r_val = fp::fix_to_float(r.unwrap());
             assert_eq!(r.unwrap(), c_fp, "Wrong value after div.");
             assert!(
@@ -185,4 +327,321 @@ mod test {
         run_test(-1.0, 666.0);
         run_test(-500.0, 50.0);
     }
+
+    #[test]
+    fn test_rounding_mode() {
+        fn run_test(op: OpCodes, mode: fp::Rounding, a: i32, b: i32) -> i32 {
+            let config = VmConfig::new().rounding(mode);
+            let mut vm = Vm::with_config(config).unwrap();
+            vm.data_stack.push(b);
+            vm.data_stack.push(a);
+            assert!(vm.load(&[op as u8]));
+            vm.cycle_once().unwrap();
+            vm.data_stack.pop().unwrap()
+        }
+        // -1.5 and -2.5 LSBs.
+        let mut vm = Vm::new();
+        vm.data_stack.push(0x8000);
+        vm.data_stack.push(-3);
+        assert!(vm.load(&[OpCodes::Mul as u8]));
+        vm.cycle_once().unwrap();
+        assert_eq!(
+            vm.data_stack.pop().unwrap(),
+            -2,
+            "Mul failed to round down by default!"
+        );
+        assert_eq!(run_test(OpCodes::Mul, fp::Rounding::Floor, -3, 0x8000), -2);
+        assert_eq!(
+            run_test(OpCodes::Mul, fp::Rounding::Truncate, -3, 0x8000),
+            -1
+        );
+        assert_eq!(
+            run_test(OpCodes::Mul, fp::Rounding::HalfEven, -3, 0x8000),
+            -2
+        );
+        assert_eq!(
+            run_test(OpCodes::Mul, fp::Rounding::HalfEven, -5, 0x8000),
+            -2
+        );
+        assert_eq!(
+            run_test(OpCodes::Mul, fp::Rounding::HalfAwayFromZero, -5, 0x8000),
+            -3
+        );
+        // 1 / 3 is 0.333... and 2 / 3 is 0.666... of an LSB.
+        let three = fp::float_to_fix(3.0);
+        assert_eq!(run_test(OpCodes::Div, fp::Rounding::Floor, 2, three), 0);
+        assert_eq!(run_test(OpCodes::Div, fp::Rounding::Floor, -2, three), -1);
+        assert_eq!(run_test(OpCodes::Div, fp::Rounding::Truncate, 2, three), 0);
+        assert_eq!(run_test(OpCodes::Div, fp::Rounding::Truncate, -2, three), 0);
+        assert_eq!(run_test(OpCodes::Div, fp::Rounding::HalfEven, 2, three), 1);
+        assert_eq!(run_test(OpCodes::Div, fp::Rounding::HalfEven, 1, three), 0);
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        fn run_test(op: u8, policy: fp::Overflow, a: f32, b: f32) -> Result<i32, VmError> {
+            let config = VmConfig::new().overflow(policy);
+            let mut vm = Vm::with_config(config).unwrap();
+            vm.data_stack.push(fp::float_to_fix(b));
+            vm.data_stack.push(fp::float_to_fix(a));
+            assert!(vm.load(&[op]));
+            vm.cycle_once()?;
+            Ok(vm.data_stack.pop().unwrap())
+        }
+        let max = i32::MAX;
+        let min = i32::MIN;
+        // 30000 + 30000, 30000 - -30000 (b - a), 200 * -200 and 1000 / 0.01 all overflow.
+        let cases = [
+            (OpCodes::Add as u8, 30000.0, 30000.0, max),
+            (OpCodes::Sub as u8, -30000.0, 30000.0, max),
+            (OpCodes::Mul as u8, 200.0, -200.0, min),
+            (OpCodes::Div as u8, 1000.0, 0.01, max),
+        ];
+        for (op, a, b, limit) in cases.iter() {
+            let wrapped = run_test(*op, fp::Overflow::Wrapping, *a, *b).unwrap();
+            assert_ne!(wrapped, *limit, "Wrapping saturated!");
+            assert_eq!(run_test(*op, fp::Overflow::Saturating, *a, *b), Ok(*limit));
+            assert_eq!(
+                run_test(*op, fp::Overflow::Trapping, *a, *b),
+                Err(VmError::Overflow)
+            );
+        }
+        assert_eq!(
+            run_test(OpCodes::Add as u8, fp::Overflow::Trapping, 1.0, 2.0),
+            Ok(fp::float_to_fix(3.0))
+        );
+        // Trapping leaves the operands in place.
+        let config = VmConfig::new().overflow(fp::Overflow::Trapping);
+        let mut vm = Vm::with_config(config).unwrap();
+        vm.data_stack.push(max);
+        vm.data_stack.push(1);
+        assert!(vm.load(&[OpCodes::Add as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::Overflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(
+            vm.data_stack.as_slice(),
+            &[max, 1],
+            "Operands not restored!"
+        );
+    }
+
+    #[test]
+    fn test_div_by_zero() {
+        let mut vm = init_vm();
+        let a_fp = fp::float_to_fix(0.0);
+        let b_fp = fp::float_to_fix(12.0);
+        vm.data_stack.push(a_fp);
+        vm.data_stack.push(b_fp);
+        let code = [OpCodes::Div as u8; 1];
+        vm.load(&code);
+        assert_eq!(vm.cycle_once(), Err(VmError::DivideByZero));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.pop(), Some(b_fp), "Operands not restored!");
+        assert_eq!(vm.data_stack.pop(), Some(a_fp), "Operands not restored!");
+    }
+
+    #[test]
+    fn test_div_by_zero_saturate() {
+        fn run_test(a: i32) -> i32 {
+            let config = VmConfig::new().div_zero(fp::DivZero::Saturate);
+            let mut vm = Vm::with_config(config).unwrap();
+            vm.data_stack.push(0);
+            vm.data_stack.push(a);
+            assert!(vm.load(&[OpCodes::Div as u8]));
+            vm.cycle_once().unwrap();
+            assert_eq!(vm.pc, 1, "Failed to increment program counter.");
+            let r = vm.data_stack.pop().unwrap();
+            assert!(vm.data_stack.empty(), "Operands left on the stack!");
+            r
+        }
+        assert_eq!(run_test(fp::float_to_fix(12.0)), i32::MAX);
+        assert_eq!(run_test(fp::float_to_fix(-12.0)), i32::MIN);
+        assert_eq!(run_test(0), 0);
+    }
+
+    #[test]
+    fn test_underflow() {
+        for op in [OpCodes::Add, OpCodes::Sub, OpCodes::Mul, OpCodes::Div] {
+            let mut vm = init_vm();
+            vm.data_stack.push(fp::float_to_fix(1.0));
+            let code = [op as u8; 1];
+            vm.load(&code);
+            assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+            assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+            assert_eq!(
+                vm.data_stack.pop(),
+                Some(fp::float_to_fix(1.0)),
+                "Underflow consumed the operand!"
+            );
+        }
+    }
+
+    fn run_ext_test(config: VmConfig, op: u8, args: &[i32]) -> Result<i32, VmError> {
+        let mut vm = Vm::with_config(config).unwrap();
+        for a in args.iter() {
+            vm.data_stack.push(*a);
+        }
+        assert!(vm.load(&[OpCodes::Ext as u8, op]));
+        vm.cycle_once()?;
+        assert_eq!(vm.pc, 2, "Failed to increment program counter.");
+        let r = vm.data_stack.pop().unwrap();
+        assert!(vm.data_stack.empty(), "Operands left on the stack!");
+        Ok(r)
+    }
+
+    #[test]
+    fn test_ext_ops() {
+        let fix = fp::float_to_fix;
+        let run = |op: ExtOpCodes, args: &[f32]| {
+            let args: Vec<i32> = args.iter().map(|a| fix(*a)).collect();
+            run_ext_test(VmConfig::new(), op as u8, &args)
+        };
+        // Mod divides the top of the stack by the value below it, like Div.
+        assert_eq!(run(ExtOpCodes::Mod, &[2.0, -7.5]), Ok(fix(-1.5)));
+        assert_eq!(run(ExtOpCodes::Mod, &[-2.0, 7.5]), Ok(fix(1.5)));
+        assert_eq!(run(ExtOpCodes::Neg, &[2.25]), Ok(fix(-2.25)));
+        assert_eq!(run(ExtOpCodes::Abs, &[-2.25]), Ok(fix(2.25)));
+        assert_eq!(run(ExtOpCodes::Min, &[-1.0, 0.5]), Ok(fix(-1.0)));
+        assert_eq!(run(ExtOpCodes::Max, &[-1.0, 0.5]), Ok(fix(0.5)));
+        assert_eq!(run(ExtOpCodes::Floor, &[-1.25]), Ok(fix(-2.0)));
+        assert_eq!(run(ExtOpCodes::Ceil, &[-1.25]), Ok(fix(-1.0)));
+        assert_eq!(run(ExtOpCodes::Frac, &[-1.25]), Ok(fix(0.75)));
+        let config = VmConfig::new;
+        assert_eq!(
+            run_ext_test(config(), ExtOpCodes::Mod as u8, &[-1, i32::MIN]),
+            Ok(0)
+        );
+        assert_eq!(
+            run_ext_test(config(), ExtOpCodes::Floor as u8, &[i32::MIN]),
+            Ok(i32::MIN)
+        );
+        assert_eq!(
+            run_ext_test(config(), ExtOpCodes::Frac as u8, &[i32::MIN]),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn test_ext_overflow() {
+        let config = |policy| VmConfig::new().overflow(policy);
+        let max = i32::MAX;
+        let min = i32::MIN;
+        let cases = [
+            (ExtOpCodes::Neg as u8, min, max),
+            (ExtOpCodes::Abs as u8, min, max),
+            (ExtOpCodes::Ceil as u8, max, max),
+        ];
+        for (op, a, limit) in cases.iter() {
+            assert_eq!(
+                run_ext_test(config(fp::Overflow::Wrapping), *op, &[*a]),
+                Ok(min)
+            );
+            assert_eq!(
+                run_ext_test(config(fp::Overflow::Saturating), *op, &[*a]),
+                Ok(*limit)
+            );
+            // Trapping leaves the operand in place.
+            let mut vm = Vm::with_config(config(fp::Overflow::Trapping)).unwrap();
+            vm.data_stack.push(*a);
+            assert!(vm.load(&[OpCodes::Ext as u8, *op]));
+            assert_eq!(vm.cycle_once(), Err(VmError::Overflow));
+            assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+            assert_eq!(vm.data_stack.as_slice(), &[*a], "Operand not restored!");
+        }
+    }
+
+    #[test]
+    fn test_mod_by_zero() {
+        let a = fp::float_to_fix(-12.0);
+        let mut vm = init_vm();
+        vm.data_stack.push(0);
+        vm.data_stack.push(a);
+        assert!(vm.load(&[OpCodes::Ext as u8, ExtOpCodes::Mod as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::DivideByZero));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.as_slice(), &[0, a], "Operands not restored!");
+        // Saturating gives the same value as Div, and the same as IMod.
+        let config = VmConfig::new().div_zero(fp::DivZero::Saturate);
+        for (args, expected) in [([0, a], i32::MIN), ([0, -a], i32::MAX), ([0, 0], 0)].iter() {
+            assert_eq!(
+                run_ext_test(config, ExtOpCodes::Mod as u8, args),
+                Ok(*expected)
+            );
+            assert_eq!(
+                run_ext_test(config, ExtOpCodes::IMod as u8, args),
+                Ok(*expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_int_ops() {
+        let config = VmConfig::new;
+        let run = |op: ExtOpCodes, args: &[i32]| run_ext_test(config(), op as u8, args);
+        assert_eq!(run(ExtOpCodes::IMul, &[-7, 6]), Ok(-42));
+        // IDiv divides the top of the stack by the value below it, like Div, and truncates.
+        assert_eq!(run(ExtOpCodes::IDiv, &[2, -7]), Ok(-3));
+        assert_eq!(run(ExtOpCodes::IDiv, &[-2, 7]), Ok(-3));
+        assert_eq!(run(ExtOpCodes::IAdd, &[-7, 6]), Ok(-1));
+        // IMod is the remainder of IDiv, with the sign of the dividend.
+        assert_eq!(run(ExtOpCodes::IMod, &[2, -7]), Ok(-1));
+        assert_eq!(run(ExtOpCodes::IMod, &[-2, 7]), Ok(1));
+        assert_eq!(run(ExtOpCodes::IMod, &[-1, i32::MIN]), Ok(0));
+        assert_eq!(run(ExtOpCodes::IToF, &[-3]), Ok(fp::float_to_fix(-3.0)));
+        let fix = fp::float_to_fix;
+        assert_eq!(run(ExtOpCodes::FToI, &[fix(-2.75)]), Ok(-2));
+        assert_eq!(run(ExtOpCodes::FToIR, &[fix(-2.75)]), Ok(-3));
+        assert_eq!(run(ExtOpCodes::FToIR, &[fix(2.5)]), Ok(3));
+        assert_eq!(run(ExtOpCodes::FToI, &[i32::MIN]), Ok(-32768));
+        // Rounding modes only apply to 16.16 results.
+        let config = VmConfig::new().rounding(fp::Rounding::HalfEven);
+        assert_eq!(run_ext_test(config, ExtOpCodes::IDiv as u8, &[3, 2]), Ok(0));
+    }
+
+    #[test]
+    fn test_int_overflow() {
+        let config = |policy| VmConfig::new().overflow(policy);
+        let cases = [
+            (ExtOpCodes::IMul as u8, vec![1 << 16, 1 << 16], i32::MAX),
+            (ExtOpCodes::IDiv as u8, vec![-1, i32::MIN], i32::MAX),
+            (ExtOpCodes::IAdd as u8, vec![1, i32::MAX], i32::MAX),
+            (ExtOpCodes::IAdd as u8, vec![-1, i32::MIN], i32::MIN),
+            (ExtOpCodes::IToF as u8, vec![-40000], i32::MIN),
+        ];
+        for (op, args, limit) in cases.iter() {
+            assert_eq!(
+                run_ext_test(config(fp::Overflow::Saturating), *op, args),
+                Ok(*limit)
+            );
+            let mut vm = Vm::with_config(config(fp::Overflow::Trapping)).unwrap();
+            for a in args.iter() {
+                vm.data_stack.push(*a);
+            }
+            assert!(vm.load(&[OpCodes::Ext as u8, *op]));
+            assert_eq!(vm.cycle_once(), Err(VmError::Overflow));
+            assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+            assert_eq!(
+                vm.data_stack.as_slice(),
+                &args[..],
+                "Operands not restored!"
+            );
+        }
+    }
+
+    #[test]
+    fn test_int_div_by_zero() {
+        for op in [ExtOpCodes::IDiv as u8, ExtOpCodes::IMod as u8].iter() {
+            let mut vm = init_vm();
+            vm.data_stack.push(0);
+            vm.data_stack.push(5);
+            assert!(vm.load(&[OpCodes::Ext as u8, *op]));
+            assert_eq!(vm.cycle_once(), Err(VmError::DivideByZero));
+            assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+            assert_eq!(vm.data_stack.as_slice(), &[0, 5], "Operands not restored!");
+            let config = VmConfig::new().div_zero(fp::DivZero::Saturate);
+            assert_eq!(run_ext_test(config, *op, &[0, -5]), Ok(i32::MIN));
+            assert_eq!(run_ext_test(config, *op, &[0, 5]), Ok(i32::MAX));
+            assert_eq!(run_ext_test(config, *op, &[0, 0]), Ok(0));
+        }
+    }
 }
@@ -1,86 +1,56 @@
 use super::opcodes::*;
+use super::VmError;
+use super::{pop, require};
+use crate::fp;
 use crate::stk::Stack;
-use crate::vm::fp;
 
-pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) {
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
     let op_type = BitOpTypes::from(inst);
     match op_type {
-        BitOpTypes::Shl => {
-            if let Some(shift_amt) = vm.data_stack.pop() {
-                let shift_amt = fp::fix_to_float(shift_amt) as i32;
-                if shift_amt >= 0 {
-                    if let Some(val) = vm.data_stack.pop() {
-                        vm.data_stack.push(val << shift_amt);
-                    }
-                }
-            }
-        }
-        BitOpTypes::Shr => {
-            if let Some(shift_amt) = vm.data_stack.pop() {
-                let shift_amt = fp::fix_to_float(shift_amt) as i32;
-                if shift_amt >= 0 {
-                    if let Some(val) = vm.data_stack.pop() {
-                        vm.data_stack.push(val >> shift_amt);
-                    }
-                }
-            }
-        }
-        BitOpTypes::Rotl => {
-            if let Some(rot_amt) = vm.data_stack.pop() {
-                let rot_amt = fp::fix_to_float(rot_amt) as i32;
-                if rot_amt >= 0 {
-                    if let Some(val) = vm.data_stack.pop() {
-                        let mask = (0xFFFFFFFFu32 as i32) << (32 - rot_amt) as i32;
-                        let masked_val: i32 = val & mask;
-                        let rot_bits: i32 = (masked_val as u32 >> (32 - rot_amt)) as i32;
-                        vm.data_stack.push((val << rot_amt) | rot_bits);
-                    }
-                }
-            }
-        }
-        BitOpTypes::Rotr => {
-            if let Some(rot_amt) = vm.data_stack.pop() {
-                let rot_amt = fp::fix_to_float(rot_amt) as i32;
-                if rot_amt >= 0 {
-                    if let Some(val) = vm.data_stack.pop() {
-                        let mask = (0xFFFFFFFFu32 as i32) >> (32 - rot_amt) as i32;
-                        let masked_val: i32 = val & mask;
-                        let rot_bits: i32 = ((masked_val as u32) << (32 - rot_amt)) as i32;
-                        vm.data_stack.push((val >> rot_amt) | rot_bits);
-                    }
-                }
-            }
-        }
+        BitOpTypes::Shl => op_shift(vm, |val, amt| val << amt)?,
+        BitOpTypes::Shr => op_shift(vm, |val, amt| val >> amt)?,
+        BitOpTypes::Rotl => op_shift(vm, i32::rotate_left)?,
+        BitOpTypes::Rotr => op_shift(vm, i32::rotate_right)?,
         BitOpTypes::And => {
-            if let Some(a) = vm.data_stack.pop() {
-                if let Some(b) = vm.data_stack.pop() {
-                    vm.data_stack.push(a & b);
-                }
-            }
+            require(&vm.data_stack, 2)?;
+            let a = pop(&mut vm.data_stack)?;
+            let b = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(a & b);
         }
         BitOpTypes::Or => {
-            if let Some(a) = vm.data_stack.pop() {
-                if let Some(b) = vm.data_stack.pop() {
-                    let val = a | b;
-                    vm.data_stack.push(val);
-                }
-            }
+            require(&vm.data_stack, 2)?;
+            let a = pop(&mut vm.data_stack)?;
+            let b = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(a | b);
         }
         BitOpTypes::Xor => {
-            if let Some(a) = vm.data_stack.pop() {
-                if let Some(b) = vm.data_stack.pop() {
-                    let val = a ^ b;
-                    vm.data_stack.push(val);
-                }
-            }
+            require(&vm.data_stack, 2)?;
+            let a = pop(&mut vm.data_stack)?;
+            let b = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(a ^ b);
         }
         BitOpTypes::Not => {
-            if let Some(a) = vm.data_stack.pop() {
-                vm.data_stack.push(!a);
-            }
+            let a = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(!a);
         }
-        _ => {}
+        _ => return Err(super::invalid_opcode(vm, inst)),
     }
+    Ok(())
+}
+
+// Shifts or rotates the second value by the integer part of the top value, truncated toward
+// zero. Amounts outside 0 to 31 raise a domain fault, leaving both operands on the stack.
+fn op_shift(vm: &mut super::Vm, op: fn(i32, u32) -> i32) -> Result<(), VmError> {
+    require(&vm.data_stack, 2)?;
+    let amt = pop(&mut vm.data_stack)?;
+    let shift = fp::fix_to_int(amt, fp::Rounding::Truncate);
+    if !(0..32).contains(&shift) {
+        vm.data_stack.push(amt);
+        return Err(VmError::Domain);
+    }
+    let val = pop(&mut vm.data_stack)?;
+    vm.data_stack.push(op(val, shift as u32));
+    Ok(())
 }
 
 #[cfg(test)]
@@ -117,10 +87,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Shl as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Shiftl failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -138,10 +108,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Shl as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Shiftl failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -162,10 +132,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Shr as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Shiftr failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -183,10 +153,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Shr as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Shiftr failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -207,10 +177,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Rotl as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Rotl failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -228,10 +198,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Rotl as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Rotl failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -252,10 +222,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Rotr as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Rotr failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -266,17 +236,17 @@ mod test {
 
         let mut vm = init_vm();
         let a = 0xFFFFFFF1u32 as i32;
-        let expected = 0xFFFFFFFCu32 as i32;
+        let expected = 0x7FFFFFFCu32 as i32;
         let rot_amt = fp::float_to_fix(2.0);
         vm.data_stack.push(a);
         vm.data_stack.push(rot_amt);
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Rotr as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Rotr failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -286,6 +256,69 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_shift_amounts() {
+        fn run_test(op: u8, val: u32, amt: f32, expected: Result<u32, VmError>) {
+            let mut vm = init_vm();
+            vm.data_stack.push(val as i32);
+            vm.data_stack.push(fp::float_to_fix(amt));
+            let code = [op; 1];
+            vm.load(&code);
+            match expected {
+                Ok(expected) => {
+                    vm.cycle_once().unwrap();
+                    assert_eq!(vm.pc, 1, "{:x} failed to increment program counter!", op);
+                    assert_eq!(
+                        vm.data_stack.pop().unwrap() as u32,
+                        expected,
+                        "{:x} by {} gave the wrong result!",
+                        op,
+                        amt
+                    );
+                    assert!(
+                        vm.data_stack.empty(),
+                        "{:x} left operands on the stack!",
+                        op
+                    );
+                }
+                Err(e) => {
+                    assert_eq!(
+                        vm.cycle_once(),
+                        Err(e),
+                        "{:x} by {} did not fault!",
+                        op,
+                        amt
+                    );
+                    assert_eq!(vm.pc, 0, "{:x} modified the pc on a fault!", op);
+                    assert_eq!(vm.data_stack.pop().unwrap(), fp::float_to_fix(amt));
+                    assert_eq!(vm.data_stack.pop().unwrap() as u32, val);
+                }
+            }
+        }
+        let val = 0x8000_0001;
+        for op in [
+            OpCodes::Shl as u8,
+            OpCodes::Shr as u8,
+            OpCodes::Rotl as u8,
+            OpCodes::Rotr as u8,
+        ] {
+            run_test(op, val, 0.0, Ok(val));
+            run_test(op, val, 0.5, Ok(val));
+            run_test(op, val, 32.0, Err(VmError::Domain));
+            run_test(op, val, 33.0, Err(VmError::Domain));
+            run_test(op, val, 40.0, Err(VmError::Domain));
+            run_test(op, val, -1.0, Err(VmError::Domain));
+            run_test(op, val, -0.5, Ok(val));
+            run_test(op, val, -1.5, Err(VmError::Domain));
+        }
+        run_test(OpCodes::Shl as u8, val, 31.0, Ok(0x8000_0000));
+        run_test(OpCodes::Shl as u8, val, 31.75, Ok(0x8000_0000));
+        run_test(OpCodes::Shr as u8, val, 31.0, Ok(0xFFFF_FFFF));
+        run_test(OpCodes::Shr as u8, 0x4000_0001, 31.0, Ok(0));
+        run_test(OpCodes::Rotl as u8, val, 31.0, Ok(0xC000_0000));
+        run_test(OpCodes::Rotr as u8, val, 31.0, Ok(0x0000_0003));
+    }
+
     #[test]
     fn test_and() {
         let mut vm = init_vm();
@@ -297,10 +330,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::And as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "And failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -321,10 +354,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Or as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Or failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -345,10 +378,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Xor as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Xor failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -367,10 +400,10 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Not as u8;
         vm.load(&code);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Not failed to increment program counter!");
         let result = vm.data_stack.pop();
-        assert!(!result.is_none(), "NONE on stack pop!");
+        assert!(result.is_some(), "NONE on stack pop!");
         assert_eq!(
             result.unwrap(),
             expected,
@@ -0,0 +1,142 @@
+use super::opcodes::*;
+use super::VmError;
+use super::{pop, require};
+
+// The value comparisons push for true. All bits are set so And, Or, Xor and Not combine results
+// as booleans.
+const TRUE: i32 = -1;
+const FALSE: i32 = 0;
+
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
+    let op_type = CompareOpTypes::from(inst);
+    match op_type {
+        CompareOpTypes::Eq => op_compare(vm, |a, b| b == a),
+        CompareOpTypes::Ne => op_compare(vm, |a, b| b != a),
+        CompareOpTypes::Lt => op_compare(vm, |a, b| b < a),
+        CompareOpTypes::Le => op_compare(vm, |a, b| b <= a),
+        CompareOpTypes::Gt => op_compare(vm, |a, b| b > a),
+        CompareOpTypes::Ge => op_compare(vm, |a, b| b >= a),
+        CompareOpTypes::Ltu => op_compare(vm, |a, b| (b as u32) < (a as u32)),
+        CompareOpTypes::Gtu => op_compare(vm, |a, b| (b as u32) > (a as u32)),
+        _ => Err(super::invalid_opcode(vm, inst)),
+    }
+}
+
+// Replaces the top two values on the data stack with TRUE if cond(top, next) holds, else FALSE.
+fn op_compare<F>(vm: &mut super::Vm, cond: F) -> Result<(), VmError>
+where
+    F: Fn(i32, i32) -> bool,
+{
+    require(&vm.data_stack, 2)?;
+    let a = pop(&mut vm.data_stack)?;
+    let b = pop(&mut vm.data_stack)?;
+    vm.data_stack.push(if cond(a, b) { TRUE } else { FALSE });
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fp;
+    use crate::vm::StopReason;
+    use crate::vm::Vm;
+
+    fn run_test(op: u8, a: i32, b: i32) -> i32 {
+        let mut vm = Vm::new();
+        vm.data_stack.push(a);
+        vm.data_stack.push(b);
+        assert!(vm.load(&[OpCodes::Ext as u8, op]));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 2, "Compare failed to increment program counter!");
+        let r = vm.data_stack.pop().unwrap();
+        assert!(vm.data_stack.empty(), "Compare left operands on the stack!");
+        r
+    }
+
+    #[test]
+    fn test_compare() {
+        let one = fp::float_to_fix(1.0);
+        let neg = fp::float_to_fix(-2.5);
+        // `a b LT` is a < b, like the branches.
+        let cases = [
+            (ExtOpCodes::Eq as u8, one, one, TRUE),
+            (ExtOpCodes::Eq as u8, one, neg, FALSE),
+            (ExtOpCodes::Ne as u8, one, neg, TRUE),
+            (ExtOpCodes::Ne as u8, neg, neg, FALSE),
+            (ExtOpCodes::Lt as u8, neg, one, TRUE),
+            (ExtOpCodes::Lt as u8, one, one, FALSE),
+            (ExtOpCodes::Le as u8, one, one, TRUE),
+            (ExtOpCodes::Le as u8, one, neg, FALSE),
+            (ExtOpCodes::Gt as u8, one, neg, TRUE),
+            (ExtOpCodes::Gt as u8, one, one, FALSE),
+            (ExtOpCodes::Ge as u8, one, one, TRUE),
+            (ExtOpCodes::Ge as u8, neg, one, FALSE),
+            // Unsigned, so negative values compare above positive ones.
+            (ExtOpCodes::Ltu as u8, one, neg, TRUE),
+            (ExtOpCodes::Ltu as u8, neg, one, FALSE),
+            (ExtOpCodes::Gtu as u8, neg, one, TRUE),
+            (ExtOpCodes::Gtu as u8, one, one, FALSE),
+        ];
+        for (op, a, b, expected) in cases.iter() {
+            assert_eq!(
+                run_test(*op, *a, *b),
+                *expected,
+                "Wrong result for {:#04x}",
+                op
+            );
+        }
+    }
+
+    #[test]
+    fn test_combine_with_bit_ops() {
+        // Evaluates (1 < 2) op (3 == 4) with a real program.
+        fn run_program(op: OpCodes) -> i32 {
+            let mut code = Vec::new();
+            for (a, b, cmp) in [(1, 2, ExtOpCodes::Lt as u8), (3, 4, ExtOpCodes::Eq as u8)].iter() {
+                for val in [*a, *b].iter() {
+                    code.push(OpCodes::PushImm as u8);
+                    code.extend_from_slice(&fp::float_to_fix(*val as f32).to_le_bytes());
+                }
+                code.push(OpCodes::Ext as u8);
+                code.push(*cmp);
+            }
+            code.push(op as u8);
+            code.push(OpCodes::Brk as u8);
+            let mut vm = Vm::new();
+            assert!(vm.load(&code));
+            let result = vm.run(100);
+            assert_eq!(result.reason, StopReason::Halted, "Program did not halt!");
+            let r = vm.data_stack.pop().unwrap();
+            assert!(vm.data_stack.empty(), "Program left data on the stack!");
+            r
+        }
+        assert_eq!(run_program(OpCodes::And), FALSE);
+        assert_eq!(run_program(OpCodes::Or), TRUE);
+        assert_eq!(run_program(OpCodes::Xor), TRUE);
+        // Not flips a result.
+        let mut vm = Vm::new();
+        vm.data_stack.push(run_test(ExtOpCodes::Ne as u8, 0, 0));
+        assert!(vm.load(&[OpCodes::Not as u8]));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.data_stack.pop(), Some(TRUE), "Not false isn't true!");
+    }
+
+    #[test]
+    fn test_underflow() {
+        let mut vm = Vm::new();
+        vm.data_stack.push(1);
+        assert!(vm.load(&[OpCodes::Ext as u8, ExtOpCodes::Eq as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.as_slice(), &[1], "Operand consumed!");
+    }
+
+    #[test]
+    fn test_invalid_compare() {
+        let inst = ExtOpCodes::Gtu as u8 + 1;
+        let mut vm = Vm::new();
+        assert!(vm.load(&[OpCodes::Ext as u8, inst]));
+        assert_eq!(vm.cycle_once(), Err(VmError::InvalidOpcode(inst, 1)));
+        assert_eq!(vm.pc, 0, "PC moved past an invalid instruction!");
+    }
+}
@@ -0,0 +1,120 @@
+//! Per instance sizing for the vm.
+
+use super::{NUM_PORTS, RAM_SIZE};
+use crate::fp::{DivZero, Overflow, Rounding};
+use crate::stk::MAX_STACK_SIZE;
+
+// Sizes for a vm built with Vm::with_config. Starts out matching Vm::new, which is also the
+// largest vm the ISA can address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VmConfig {
+    pub(super) ram_size: usize,
+    pub(super) stack_depth: usize,
+    pub(super) num_ports: usize,
+    pub(super) rounding: Rounding,
+    pub(super) overflow: Overflow,
+    pub(super) div_zero: DivZero,
+}
+
+impl Default for VmConfig {
+    fn default() -> VmConfig {
+        VmConfig {
+            ram_size: RAM_SIZE,
+            stack_depth: MAX_STACK_SIZE,
+            num_ports: NUM_PORTS,
+            rounding: Rounding::Floor,
+            overflow: Overflow::Wrapping,
+            div_zero: DivZero::Fault,
+        }
+    }
+}
+
+impl VmConfig {
+    pub fn new() -> VmConfig {
+        VmConfig::default()
+    }
+
+    // Bytes of ram, at most 2^15 since addresses are the integer part of a 16.16 value.
+    pub fn ram_size(mut self, ram_size: usize) -> VmConfig {
+        self.ram_size = ram_size;
+        self
+    }
+
+    // Number of values each of the data, call and port stacks can hold, at most 2^16.
+    pub fn stack_depth(mut self, stack_depth: usize) -> VmConfig {
+        self.stack_depth = stack_depth;
+        self
+    }
+
+    // Number of ports, at most 8 since port ops select one with three bits.
+    pub fn num_ports(mut self, num_ports: usize) -> VmConfig {
+        self.num_ports = num_ports;
+        self
+    }
+
+    // How MUL, DIV and ARND round their results. IDIV, FTOI and FTOIR always round their own way.
+    pub fn rounding(mut self, rounding: Rounding) -> VmConfig {
+        self.rounding = rounding;
+        self
+    }
+
+    // What results too large to hold do: those of ADD, SUB, MUL, DIV, NEG, ABS, CEIL, EXP, ITOF
+    // and ARND past 16.16, of IADD, IMUL and IDIV past 32 bits, and MAC sums past 64 bits.
+    pub fn overflow(mut self, overflow: Overflow) -> VmConfig {
+        self.overflow = overflow;
+        self
+    }
+
+    // What DIV, MOD, IDIV and IMOD do with a zero divisor.
+    pub fn div_zero(mut self, div_zero: DivZero) -> VmConfig {
+        self.div_zero = div_zero;
+        self
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (1..=RAM_SIZE).contains(&self.ram_size)
+            && (1..=MAX_STACK_SIZE).contains(&self.stack_depth)
+            && self.num_ports <= NUM_PORTS
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let config = VmConfig::new();
+        assert_eq!(config, VmConfig::default());
+        assert_eq!(config.ram_size, RAM_SIZE);
+        assert_eq!(config.stack_depth, MAX_STACK_SIZE);
+        assert_eq!(config.num_ports, NUM_PORTS);
+        assert_eq!(config.rounding, Rounding::Floor);
+        assert_eq!(config.overflow, Overflow::Wrapping);
+        assert_eq!(config.div_zero, DivZero::Fault);
+        assert!(config.is_valid());
+    }
+
+    #[test]
+    fn test_is_valid() {
+        let config = VmConfig::new()
+            .ram_size(256)
+            .stack_depth(16)
+            .num_ports(0)
+            .rounding(Rounding::HalfEven)
+            .overflow(Overflow::Trapping)
+            .div_zero(DivZero::Saturate);
+        assert_eq!(config.ram_size, 256);
+        assert_eq!(config.stack_depth, 16);
+        assert_eq!(config.num_ports, 0);
+        assert_eq!(config.rounding, Rounding::HalfEven);
+        assert_eq!(config.overflow, Overflow::Trapping);
+        assert_eq!(config.div_zero, DivZero::Saturate);
+        assert!(config.is_valid());
+        assert!(!VmConfig::new().ram_size(0).is_valid());
+        assert!(!VmConfig::new().ram_size(RAM_SIZE + 1).is_valid());
+        assert!(!VmConfig::new().stack_depth(0).is_valid());
+        assert!(!VmConfig::new().stack_depth(MAX_STACK_SIZE + 1).is_valid());
+        assert!(!VmConfig::new().num_ports(NUM_PORTS + 1).is_valid());
+    }
+}
@@ -0,0 +1,325 @@
+use super::opcodes::*;
+use super::VmError;
+use super::{pop, push, require};
+use crate::fp;
+use crate::stk::Stack;
+
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
+    let op_type = ControlOpTypes::from(inst);
+    let addr_mode = OpAddrMode::from(inst);
+    match op_type {
+        ControlOpTypes::Jmp => {
+            require(&vm.data_stack, addr_mode.stack_args())?;
+            vm.pc = super::get_addr(vm, &addr_mode, 1, super::Access::Read)? as usize;
+        }
+        ControlOpTypes::Call => {
+            require(&vm.data_stack, addr_mode.stack_args())?;
+            if vm.call_stack.full() {
+                return Err(VmError::StackOverflow);
+            }
+            let addr_arg = vm.data_stack.peek();
+            let target = super::get_addr(vm, &addr_mode, 1, super::Access::Read)? as usize;
+            // Return addresses are kept in 16.16 so they survive MovFromRts/JMP round trips.
+            let ret_addr = super::return_addr(vm.pc).inspect_err(|_| {
+                if let (1, Some(arg)) = (addr_mode.stack_args(), addr_arg) {
+                    vm.data_stack.push(arg);
+                }
+            })?;
+            push(&mut vm.call_stack, ret_addr)?;
+            vm.pc = target;
+        }
+        ControlOpTypes::Ret => vm.pc = super::pop_return_addr(vm)?,
+        ControlOpTypes::Beq => op_branch(vm, addr_mode, |a, b| b == a)?,
+        ControlOpTypes::Bneq => op_branch(vm, addr_mode, |a, b| b != a)?,
+        ControlOpTypes::Bgt => op_branch(vm, addr_mode, |a, b| b > a)?,
+        ControlOpTypes::Blt => op_branch(vm, addr_mode, |a, b| b < a)?,
+        _ => return Err(super::invalid_opcode(vm, inst)),
+    }
+    Ok(())
+}
+
+// Compares the top two values on the data stack and jumps if cond(top, next) holds.
+// The target is always decoded so the program counter skips any operand bytes.
+fn op_branch(
+    vm: &mut super::Vm,
+    addr_mode: OpAddrMode,
+    cond: fn(i32, i32) -> bool,
+) -> Result<(), VmError> {
+    require(&vm.data_stack, 2 + addr_mode.stack_args())?;
+    let a = pop(&mut vm.data_stack)?;
+    let b = pop(&mut vm.data_stack)?;
+    let target = super::get_addr(vm, &addr_mode, 1, super::Access::Read).inspect_err(|_| {
+        vm.data_stack.push(b);
+        vm.data_stack.push(a);
+    })?;
+    if cond(a, b) {
+        vm.pc = target as usize;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::Vm;
+    use crate::vm::INVALID_INTERRUPT;
+    use crate::vm::RAM_SIZE;
+
+    fn init_vm() -> Box<Vm> {
+        let vm = Vm::new();
+        assert!(vm.pc == 0);
+        assert!(vm.data_stack.empty());
+        assert!(vm.call_stack.empty());
+        for p in vm.ports.iter() {
+            assert!(p.empty());
+        }
+        for i in vm.interrupts.iter() {
+            assert_eq!(*i, INVALID_INTERRUPT);
+        }
+        vm
+    }
+
+    #[test]
+    fn test_jmp_imm() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[0] = OpCodes::JmpImm as u8;
+        code[1..5].clone_from_slice(&fp::float_to_fix(300.0).to_le_bytes());
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 300, "JmpImm jumped to the wrong address!");
+        assert!(vm.data_stack.empty(), "JmpImm modified the data stack!");
+    }
+
+    #[test]
+    fn test_jmp_idx_stk() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        let base: u16 = 0x120;
+        vm.data_stack.push(fp::float_to_fix(3.0));
+        code[0] = OpCodes::JmpIndStk as u8;
+        code[1..3].clone_from_slice(&base.to_le_bytes());
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 0x123, "JmpIndStk jumped to the wrong address!");
+        assert!(
+            vm.data_stack.empty(),
+            "JmpIndStk left the offset on the stack!"
+        );
+    }
+
+    #[test]
+    fn test_jmp_idx_imm() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        let offset: u16 = 3;
+        vm.data_stack.push(fp::float_to_fix(0x120 as f32));
+        code[0] = OpCodes::JmpIndImm as u8;
+        code[1..3].clone_from_slice(&offset.to_le_bytes());
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 0x123, "JmpIndImm jumped to the wrong address!");
+        assert!(
+            vm.data_stack.empty(),
+            "JmpIndImm left the base on the stack!"
+        );
+    }
+
+    #[test]
+    fn test_jmp_stk() {
+        let mut vm = init_vm();
+        let code = [OpCodes::JmpStk as u8; 1];
+        vm.data_stack.push(fp::float_to_fix(1234.0));
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1234, "JmpStk jumped to the wrong address!");
+        assert!(
+            vm.data_stack.empty(),
+            "JmpStk left the address on the stack!"
+        );
+        // Out of range targets fault.
+        let mut vm = init_vm();
+        vm.data_stack.push(fp::float_to_fix(-4.0));
+        assert!(vm.load(&code));
+        assert_eq!(vm.cycle_once(), Err(VmError::MemoryOutOfBounds(-4)));
+        assert_eq!(vm.pc, 0, "JmpStk jumped to a negative address!");
+        assert_eq!(vm.data_stack.pop(), Some(fp::float_to_fix(-4.0)));
+    }
+
+    #[test]
+    fn test_call_ret() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[0] = OpCodes::CallImm as u8;
+        code[1..5].clone_from_slice(&fp::float_to_fix(100.0).to_le_bytes());
+        code[100] = OpCodes::Ret as u8;
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 100, "Call jumped to the wrong address!");
+        let ret_addr = vm.call_stack.peek();
+        assert!(ret_addr.is_some(), "Call failed to push a return address!");
+        assert_eq!(
+            ret_addr.unwrap(),
+            fp::float_to_fix(5.0),
+            "Call pushed the wrong return address!"
+        );
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 5, "Ret returned to the wrong address!");
+        assert!(vm.call_stack.empty(), "Ret left data on the call stack!");
+        // Ret with nothing on the call stack faults.
+        let mut vm = init_vm();
+        let code = [OpCodes::Ret as u8; 1];
+        assert!(vm.load(&code));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 0, "Ret modified the pc with an empty call stack!");
+    }
+
+    #[test]
+    fn test_call_stk() {
+        let mut vm = init_vm();
+        let code = [OpCodes::CallStk as u8; 1];
+        vm.data_stack.push(fp::float_to_fix(42.0));
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 42, "CallStk jumped to the wrong address!");
+        assert!(
+            vm.data_stack.empty(),
+            "CallStk left the address on the stack!"
+        );
+        assert_eq!(
+            vm.call_stack.pop().unwrap(),
+            fp::float_to_fix(1.0),
+            "CallStk pushed the wrong return address!"
+        );
+    }
+
+    #[test]
+    fn test_call_at_top_of_memory() {
+        // The return address one past the end of ram can't be kept in 16.16.
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[RAM_SIZE - 1] = OpCodes::CallStk as u8;
+        assert!(vm.load(&code));
+        vm.pc = RAM_SIZE - 1;
+        vm.data_stack.push(fp::float_to_fix(42.0));
+        assert_eq!(
+            vm.cycle_once(),
+            Err(VmError::MemoryOutOfBounds(RAM_SIZE as isize))
+        );
+        assert_eq!(vm.pc, RAM_SIZE - 1, "CallStk modified the pc on a fault!");
+        assert_eq!(
+            vm.data_stack.pop().unwrap(),
+            fp::float_to_fix(42.0),
+            "CallStk lost its address on a fault!"
+        );
+        assert!(vm.call_stack.empty(), "CallStk pushed a wrapped pc!");
+    }
+
+    #[test]
+    fn test_branches() {
+        fn run_test(op: OpCodes, a: f32, b: f32, taken: bool) {
+            let mut vm = init_vm();
+            let mut code = [0u8; RAM_SIZE];
+            code[0] = op as u8;
+            code[1..5].clone_from_slice(&fp::float_to_fix(200.0).to_le_bytes());
+            vm.data_stack.push(fp::float_to_fix(a));
+            vm.data_stack.push(fp::float_to_fix(b));
+            assert!(vm.load(&code));
+            vm.cycle_once().unwrap();
+            let expected = if taken { 200 } else { 5 };
+            assert_eq!(
+                vm.pc, expected,
+                "Branch on {} and {} went the wrong way!",
+                a, b
+            );
+            assert!(vm.data_stack.empty(), "Branch left operands on the stack!");
+        }
+        run_test(OpCodes::BeqImm, 5.0, 5.0, true);
+        run_test(OpCodes::BeqImm, 5.0, -5.0, false);
+        run_test(OpCodes::BneqImm, 5.0, -5.0, true);
+        run_test(OpCodes::BneqImm, 5.0, 5.0, false);
+        run_test(OpCodes::BgtImm, 6.0, 5.0, true);
+        run_test(OpCodes::BgtImm, 5.0, 5.0, false);
+        run_test(OpCodes::BgtImm, -6.0, 5.0, false);
+        run_test(OpCodes::BltImm, -6.0, 5.0, true);
+        run_test(OpCodes::BltImm, 5.0, 5.0, false);
+        run_test(OpCodes::BltImm, 6.0, 5.0, false);
+    }
+
+    #[test]
+    fn test_branch_stk() {
+        // The target sits beneath the two compared values.
+        let mut vm = init_vm();
+        let code = [OpCodes::BeqStk as u8; 1];
+        vm.data_stack.push(fp::float_to_fix(77.0));
+        vm.data_stack.push(fp::float_to_fix(1.0));
+        vm.data_stack.push(fp::float_to_fix(1.0));
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 77, "BeqStk jumped to the wrong address!");
+        assert!(vm.data_stack.empty(), "BeqStk left data on the stack!");
+        // Not taken still consumes the target.
+        let mut vm = init_vm();
+        vm.data_stack.push(fp::float_to_fix(77.0));
+        vm.data_stack.push(fp::float_to_fix(1.0));
+        vm.data_stack.push(fp::float_to_fix(2.0));
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "BeqStk jumped when it shouldn't have!");
+        assert!(vm.data_stack.empty(), "BeqStk left data on the stack!");
+        // Too few values faults without touching the stack.
+        let mut vm = init_vm();
+        vm.data_stack.push(fp::float_to_fix(1.0));
+        vm.data_stack.push(fp::float_to_fix(1.0));
+        assert!(vm.load(&code));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 0, "BeqStk jumped with too few values!");
+        assert_eq!(vm.data_stack.len(), 2, "BeqStk consumed values on a fault!");
+    }
+
+    #[test]
+    fn test_call_overflow() {
+        let mut vm = init_vm();
+        let code = [OpCodes::CallStk as u8; 1];
+        while !vm.call_stack.full() {
+            vm.call_stack.push(0);
+        }
+        vm.data_stack.push(fp::float_to_fix(42.0));
+        assert!(vm.load(&code));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackOverflow));
+        assert_eq!(vm.pc, 0, "CallStk moved the pc on overflow!");
+        assert_eq!(vm.data_stack.pop(), Some(fp::float_to_fix(42.0)));
+    }
+
+    #[test]
+    fn test_countdown_loop() {
+        // 0: PushImm 3.0
+        // 5: PushImm 1.0   <- loop
+        // 10: Sub
+        // 11: Dup
+        // 12: PushImm 0.0
+        // 17: BgtImm 5.0
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[0] = OpCodes::PushImm as u8;
+        code[1..5].clone_from_slice(&fp::float_to_fix(3.0).to_le_bytes());
+        code[5] = OpCodes::PushImm as u8;
+        code[6..10].clone_from_slice(&fp::float_to_fix(1.0).to_le_bytes());
+        code[10] = OpCodes::Sub as u8;
+        code[11] = OpCodes::Dup as u8;
+        code[12] = OpCodes::PushImm as u8;
+        code[13..17].clone_from_slice(&fp::float_to_fix(0.0).to_le_bytes());
+        code[17] = OpCodes::BgtImm as u8;
+        code[18..22].clone_from_slice(&fp::float_to_fix(5.0).to_le_bytes());
+        assert!(vm.load(&code));
+        let mut cycles = 0;
+        while vm.pc != 22 {
+            vm.cycle_once().unwrap();
+            cycles += 1;
+            assert!(cycles < 100, "Loop never terminated!");
+        }
+        assert_eq!(cycles, 1 + 3 * 5, "Loop ran the wrong number of times!");
+        assert_eq!(vm.data_stack.pop().unwrap(), 0, "Loop counter not zero!");
+        assert!(vm.data_stack.empty(), "Loop left data on the stack!");
+    }
+}
@@ -0,0 +1,413 @@
+use super::opcodes::*;
+use super::VmError;
+use super::VmState;
+use super::INVALID_INTERRUPT;
+use super::NUM_PORTS;
+use super::RAM_SIZE;
+use super::{check_addr, pop, return_addr};
+use crate::fp;
+use crate::stk::Stack;
+
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
+    let op_type = InterruptOpTypes::from(inst);
+    match op_type {
+        InterruptOpTypes::Seti => {
+            let port = (inst & OpMasks::Port as u8) as usize;
+            if port >= vm.ports.len() {
+                return Err(super::invalid_opcode(vm, inst));
+            }
+            set_vector(vm, port)?;
+            if vm.interrupts[port] == INVALID_INTERRUPT {
+                vm.pending_interrupts[port] = false;
+            }
+        }
+        InterruptOpTypes::Setf => {
+            let fault = (inst & OpMasks::Port as u8) as usize;
+            set_vector(vm, vm.fault_vector(fault as u8))?;
+        }
+        InterruptOpTypes::Reti => {
+            vm.pc = super::pop_return_addr(vm)?;
+            vm.interrupts_enabled = true;
+            vm.interrupt_depth = vm.interrupt_depth.saturating_sub(1);
+            vm.state = vm.active_state();
+        }
+        InterruptOpTypes::Ei => vm.interrupts_enabled = true,
+        InterruptOpTypes::Di => vm.interrupts_enabled = false,
+        _ => return Err(super::invalid_opcode(vm, inst)),
+    }
+    Ok(())
+}
+
+// Pops a handler address into the given vector. A negative address uninstalls the handler.
+fn set_vector(vm: &mut super::Vm, vector: usize) -> Result<(), VmError> {
+    let addr = pop(&mut vm.data_stack)?;
+    if addr < 0 {
+        vm.interrupts[vector] = INVALID_INTERRUPT;
+    } else {
+        let handler = check_addr(vm, (addr >> 16) as isize).inspect_err(|_| {
+            vm.data_stack.push(addr);
+        })?;
+        vm.interrupts[vector] = handler as i16;
+    }
+    Ok(())
+}
+
+// Vectors into the handler of the lowest numbered pending interrupt. The interrupted pc is saved
+// on the call stack and interrupts stay disabled until the handler executes RETI. Faults if the
+// pc can't be saved as a 16.16 return address.
+pub(super) fn service(vm: &mut super::Vm) -> Result<(), VmError> {
+    if !vm.interrupts_enabled {
+        return Ok(());
+    }
+    for port in 0..vm.ports.len() {
+        if !vm.pending_interrupts[port] {
+            continue;
+        }
+        let handler = vm.interrupts[port];
+        if handler == INVALID_INTERRUPT {
+            vm.pending_interrupts[port] = false;
+            continue;
+        }
+        let ret_addr = return_addr(vm.pc)?;
+        if !vm.call_stack.push(ret_addr) {
+            return Ok(());
+        }
+        vm.pending_interrupts[port] = false;
+        vm.interrupts_enabled = false;
+        vm.interrupt_depth += 1;
+        vm.state = VmState::InInterrupt;
+        vm.pc = handler as usize;
+        return Ok(());
+    }
+    Ok(())
+}
+
+// Enters the guest's handler for a fault with the faulting pc and the fault code (both 16.16)
+// pushed on the data stack. Returns false, leaving the vm untouched, if no handler is installed,
+// the pc can't be saved as a 16.16 address or the data stack has no room for them.
+pub(super) fn trap(vm: &mut super::Vm, e: VmError) -> bool {
+    let handler = vm.interrupts[vm.fault_vector(e.code())];
+    if handler == INVALID_INTERRUPT {
+        return false;
+    }
+    let fault_pc = match return_addr(vm.pc) {
+        Ok(fault_pc) => fault_pc,
+        Err(_) => return false,
+    };
+    if !vm.data_stack.push(fault_pc) {
+        return false;
+    }
+    if !vm.data_stack.push(i32::from(e.code()) << 16) {
+        vm.data_stack.pop();
+        return false;
+    }
+    vm.pc = handler as usize;
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fp::Fix16;
+    use crate::vm::StepOutcome;
+    use crate::vm::StopReason;
+    use crate::vm::Vm;
+
+    fn init_vm() -> Box<Vm> {
+        let vm = Vm::new();
+        assert!(vm.pc == 0);
+        assert!(vm.data_stack.empty());
+        assert!(vm.call_stack.empty());
+        for p in vm.ports.iter() {
+            assert!(p.empty());
+        }
+        for i in vm.interrupts.iter() {
+            assert_eq!(*i, INVALID_INTERRUPT);
+        }
+        vm
+    }
+
+    #[test]
+    fn test_seti() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[0] = OpCodes::Seti as u8 | 3;
+        code[1] = OpCodes::Seti as u8 | 3;
+        assert!(vm.load(&code));
+        vm.data_stack.push(fp::float_to_fix(-1.0));
+        vm.data_stack.push(fp::float_to_fix(0x200 as f32));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "Seti failed to increment program counter!");
+        assert!(!vm.data_stack.empty(), "Seti popped too much!");
+        assert_eq!(vm.interrupts[3], 0x200, "Seti failed to set the vector!");
+        for (i, v) in vm.interrupts.iter().enumerate() {
+            if i != 3 {
+                assert_eq!(*v, INVALID_INTERRUPT, "Seti set the wrong vector!");
+            }
+        }
+        // Negative addresses clear the vector.
+        vm.cycle_once().unwrap();
+        assert_eq!(
+            vm.interrupts[3], INVALID_INTERRUPT,
+            "Seti failed to clear the vector!"
+        );
+        assert!(vm.data_stack.empty(), "Seti left data on the stack!");
+    }
+
+    #[test]
+    fn test_dispatch_and_reti() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[0] = OpCodes::Seti as u8 | 2;
+        code[1] = OpCodes::Pop as u8;
+        code[0x100] = OpCodes::Nop as u8;
+        code[0x101] = OpCodes::Reti as u8;
+        assert!(vm.load(&code));
+        vm.data_stack.push(fp::float_to_fix(0x100 as f32));
+        vm.cycle_once().unwrap();
+        assert!(
+            vm.port_push(2, Fix16::from_bits(66)),
+            "Host failed to push to port!"
+        );
+        // Dispatch happens before the next fetch, so the handler's first instruction runs.
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 0x101, "Interrupt did not vector to the handler!");
+        assert_eq!(vm.state(), VmState::InInterrupt);
+        assert!(
+            !vm.interrupts_enabled,
+            "Interrupts left enabled in handler!"
+        );
+        assert_eq!(
+            vm.call_stack.peek().unwrap(),
+            fp::float_to_fix(1.0),
+            "Interrupt saved the wrong pc!"
+        );
+        // No re-entry while the handler runs.
+        assert!(vm.port_push(2, Fix16::from_bits(67)));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "Reti returned to the wrong address!");
+        assert!(vm.interrupts_enabled, "Reti failed to enable interrupts!");
+        assert!(vm.call_stack.empty(), "Reti left data on the call stack!");
+        assert_eq!(
+            vm.state(),
+            VmState::Running,
+            "Reti failed to leave handler!"
+        );
+        // The second value was latched and is serviced after RETI.
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 0x101, "Latched interrupt was not serviced!");
+        assert_eq!(vm.port_pop(2), Some(Fix16::from_bits(67)));
+        assert_eq!(vm.port_pop(2), Some(Fix16::from_bits(66)));
+    }
+
+    #[test]
+    fn test_ei_di() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[0] = OpCodes::Di as u8;
+        code[1] = OpCodes::Ei as u8;
+        code[0x80] = OpCodes::Reti as u8;
+        assert!(vm.load(&code));
+        vm.interrupts[0] = 0x80;
+        vm.cycle_once().unwrap();
+        assert!(!vm.interrupts_enabled, "Di failed to disable interrupts!");
+        assert!(vm.port_push(0, Fix16::from_bits(1)));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 2, "Interrupt serviced while disabled!");
+        assert!(vm.interrupts_enabled, "Ei failed to enable interrupts!");
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 2, "Interrupt was not serviced after Ei!");
+        assert!(vm.call_stack.empty());
+    }
+
+    #[test]
+    fn test_reti_reserved_bits() {
+        let reti = OpCodes::Reti as u8;
+        assert!(matches!(
+            InterruptOpTypes::from(reti),
+            InterruptOpTypes::Reti
+        ));
+        for low in 1..8 {
+            assert!(
+                matches!(
+                    InterruptOpTypes::from(reti | low),
+                    InterruptOpTypes::Invalid
+                ),
+                "Reti decoded with low bits {:03b}!",
+                low
+            );
+        }
+        let mut vm = init_vm();
+        assert!(vm.load(&[reti | 1]));
+        vm.call_stack.push(fp::float_to_fix(0x10 as f32));
+        assert_eq!(vm.cycle_once(), Err(VmError::InvalidOpcode(reti | 1, 0)));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(
+            vm.call_stack.len(),
+            1,
+            "Invalid Reti popped the call stack!"
+        );
+    }
+
+    #[test]
+    fn test_no_handler() {
+        let mut vm = init_vm();
+        let code = [OpCodes::Ei as u8; 2];
+        assert!(vm.load(&code));
+        assert!(vm.port_push(5, Fix16::from_bits(1)));
+        assert!(
+            !vm.pending_interrupts[5],
+            "Interrupt pending without handler!"
+        );
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "Vectored without a handler!");
+        assert!(
+            !vm.port_push(8, Fix16::from_bits(1)),
+            "Pushed to a port that doesn't exist!"
+        );
+        assert_eq!(vm.port_pop(8), None);
+    }
+
+    #[test]
+    fn test_setf() {
+        let mut vm = init_vm();
+        let code = [OpCodes::Setf as u8 | 4; 1];
+        assert!(vm.load(&code));
+        vm.data_stack.push(fp::float_to_fix(0x300 as f32));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "Setf failed to increment program counter!");
+        assert_eq!(
+            vm.interrupts[NUM_PORTS + 4],
+            0x300,
+            "Setf failed to set the vector!"
+        );
+        assert_eq!(
+            vm.interrupts[4], INVALID_INTERRUPT,
+            "Setf set a port vector!"
+        );
+    }
+
+    #[test]
+    fn test_trap() {
+        let mut vm = init_vm();
+        let code = [OpCodes::Div as u8; 1];
+        assert!(vm.load(&code));
+        let div_code = VmError::DivideByZero.code() as usize;
+        vm.interrupts[NUM_PORTS + div_code] = 0x40;
+        vm.data_stack.push(0);
+        vm.data_stack.push(fp::float_to_fix(3.0));
+        assert_eq!(
+            vm.cycle_once(),
+            Ok(StepOutcome::Trapped(VmError::DivideByZero))
+        );
+        assert_eq!(vm.pc, 0x40, "Fault did not vector to the handler!");
+        assert_eq!(
+            vm.data_stack.pop(),
+            Some((div_code as i32) << 16),
+            "Wrong fault code pushed!"
+        );
+        assert_eq!(vm.data_stack.pop(), Some(0), "Wrong faulting pc pushed!");
+        assert_eq!(vm.data_stack.pop(), Some(fp::float_to_fix(3.0)));
+        assert_eq!(vm.data_stack.pop(), Some(0));
+        assert!(vm.call_stack.empty(), "Trap modified the call stack!");
+    }
+
+    #[test]
+    fn test_trap_falls_back_to_host() {
+        // No handler for this fault.
+        let mut vm = init_vm();
+        let code = [OpCodes::Pop as u8; 1];
+        assert!(vm.load(&code));
+        vm.interrupts[NUM_PORTS + VmError::DivideByZero.code() as usize] = 0x40;
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 0);
+        // No room to report the fault.
+        let mut vm = init_vm();
+        let code = [OpCodes::Dup as u8; 1];
+        assert!(vm.load(&code));
+        vm.interrupts[NUM_PORTS + VmError::StackOverflow.code() as usize] = 0x40;
+        while !vm.data_stack.full() {
+            vm.data_stack.push(1);
+        }
+        assert_eq!(vm.cycle_once(), Err(VmError::StackOverflow));
+        assert_eq!(vm.pc, 0);
+        assert!(vm.data_stack.full(), "Failed trap modified the data stack!");
+    }
+
+    #[test]
+    fn test_handler_resumes() {
+        // The handler drops the fault code and resumes after the faulting instruction.
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[0] = OpCodes::Pop as u8;
+        code[1] = OpCodes::Brk as u8;
+        code[0x40] = OpCodes::Pop as u8;
+        code[0x41] = OpCodes::PushImm as u8;
+        code[0x42..0x46].clone_from_slice(&fp::float_to_fix(1.0).to_le_bytes());
+        code[0x46] = OpCodes::Add as u8;
+        code[0x47] = OpCodes::JmpStk as u8;
+        assert!(vm.load(&code));
+        vm.interrupts[NUM_PORTS + VmError::StackUnderflow.code() as usize] = 0x40;
+        let result = vm.run(100);
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.retired, 5);
+        assert_eq!(vm.pc, 2);
+        assert!(vm.data_stack.empty());
+    }
+
+    #[test]
+    fn test_dispatch_at_top_of_memory() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[0x100] = OpCodes::Nop as u8;
+        code[0x101] = OpCodes::Reti as u8;
+        code[RAM_SIZE - 2] = OpCodes::Nop as u8;
+        code[RAM_SIZE - 1] = OpCodes::Nop as u8;
+        assert!(vm.load(&code));
+        vm.interrupts[2] = 0x100;
+        vm.pc = RAM_SIZE - 2;
+        vm.cycle_once().unwrap();
+        // The last addressable pc still fits in a 16.16 return address.
+        assert!(vm.port_push(2, Fix16::from_bits(66)));
+        vm.cycle_once().unwrap();
+        assert_eq!(
+            vm.call_stack.peek().unwrap(),
+            fp::float_to_fix((RAM_SIZE - 1) as f32),
+            "Interrupt saved the wrong pc!"
+        );
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, RAM_SIZE - 1, "Reti returned to the wrong address!");
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, RAM_SIZE);
+        // One past the end of ram does not.
+        assert!(vm.port_push(2, Fix16::from_bits(67)));
+        assert_eq!(
+            vm.cycle_once(),
+            Err(VmError::MemoryOutOfBounds(RAM_SIZE as isize))
+        );
+        assert_eq!(vm.pc, RAM_SIZE);
+        assert!(vm.call_stack.empty(), "Interrupt pushed a wrapped pc!");
+        assert_eq!(
+            vm.state(),
+            VmState::Faulted(VmError::MemoryOutOfBounds(RAM_SIZE as isize))
+        );
+    }
+
+    #[test]
+    fn test_trap_at_top_of_memory() {
+        let mut vm = init_vm();
+        let mut code = [0u8; RAM_SIZE];
+        code[RAM_SIZE - 1] = OpCodes::Nop as u8;
+        assert!(vm.load(&code));
+        vm.interrupts[NUM_PORTS + VmError::MemoryOutOfBounds(0).code() as usize] = 0x40;
+        vm.pc = RAM_SIZE - 1;
+        vm.cycle_once().unwrap();
+        // Fetching past the end faults, and the pc can't be handed to the guest handler.
+        assert_eq!(
+            vm.cycle_once(),
+            Err(VmError::MemoryOutOfBounds(RAM_SIZE as isize))
+        );
+        assert_eq!(vm.pc, RAM_SIZE);
+        assert!(vm.data_stack.empty(), "Trap pushed a wrapped pc!");
+    }
+}
@@ -0,0 +1,153 @@
+use super::opcodes::*;
+use super::VmError;
+use super::{pop, require};
+use crate::fp;
+
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
+    let op_type = MathOpTypes::from(inst);
+    match op_type {
+        MathOpTypes::Sqrt => unary_op(vm, |a, _| fp::fp_sqrt(a).ok_or(VmError::Domain)),
+        MathOpTypes::Sin => unary_op(vm, |a, _| Ok(fp::fp_sin(a))),
+        MathOpTypes::Cos => unary_op(vm, |a, _| Ok(fp::fp_cos(a))),
+        MathOpTypes::Exp => unary_op(vm, |a, vm| {
+            fp::narrow(fp::fp_exp_wide(a), vm.config.overflow).ok_or(VmError::Overflow)
+        }),
+        MathOpTypes::Log => unary_op(vm, |a, _| fp::fp_log(a).ok_or(VmError::Domain)),
+        MathOpTypes::Atan2 => {
+            // x is on top, so `y x ATAN2` gives the angle of (x, y).
+            require(&vm.data_stack, 2)?;
+            let x = pop(&mut vm.data_stack)?;
+            let y = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(fp::fp_atan2(y, x));
+            Ok(())
+        }
+        _ => Err(super::invalid_opcode(vm, inst)),
+    }
+}
+
+// Replaces the top of the data stack with f of it. If f fails the operand is put back.
+fn unary_op<F>(vm: &mut super::Vm, f: F) -> Result<(), VmError>
+where
+    F: Fn(i32, &super::Vm) -> Result<i32, VmError>,
+{
+    let a = pop(&mut vm.data_stack)?;
+    match f(a, vm) {
+        Ok(val) => {
+            vm.data_stack.push(val);
+            Ok(())
+        }
+        Err(e) => {
+            vm.data_stack.push(a);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::Vm;
+    use crate::vm::VmConfig;
+
+    fn run_test(config: VmConfig, op: OpCodes, args: &[f32]) -> Result<i32, VmError> {
+        let mut vm = Vm::with_config(config).unwrap();
+        for a in args.iter() {
+            vm.data_stack.push(fp::float_to_fix(*a));
+        }
+        assert!(vm.load(&[op as u8]));
+        vm.cycle_once()?;
+        assert_eq!(vm.pc, 1, "Math op failed to increment program counter!");
+        let r = vm.data_stack.pop().unwrap();
+        assert!(vm.data_stack.empty(), "Operands left on the stack!");
+        Ok(r)
+    }
+
+    fn assert_close(result: Result<i32, VmError>, exact: f64) {
+        let result = fp::fix_to_f64(result.unwrap());
+        assert!(
+            (result - exact).abs() <= 1.0 / 65536.0,
+            "{} is not {}",
+            result,
+            exact
+        );
+    }
+
+    #[test]
+    fn test_math_ops() {
+        let config = VmConfig::new;
+        assert_eq!(
+            run_test(config(), OpCodes::Sqrt, &[2.25]),
+            Ok(fp::float_to_fix(1.5))
+        );
+        assert_close(run_test(config(), OpCodes::Sin, &[1.0]), 1f64.sin());
+        assert_close(run_test(config(), OpCodes::Cos, &[-2.5]), (-2.5f64).cos());
+        assert_close(
+            run_test(config(), OpCodes::Atan2, &[1.0, -2.0]),
+            1f64.atan2(-2.0),
+        );
+        assert_close(run_test(config(), OpCodes::Exp, &[2.5]), 2.5f64.exp());
+        assert_close(run_test(config(), OpCodes::Log, &[10.0]), 10f64.ln());
+    }
+
+    #[test]
+    fn test_exp_overflow() {
+        let config = |policy| VmConfig::new().overflow(policy);
+        assert_eq!(
+            run_test(config(fp::Overflow::Saturating), OpCodes::Exp, &[11.0]),
+            Ok(i32::MAX)
+        );
+        assert_eq!(
+            run_test(config(fp::Overflow::Saturating), OpCodes::Exp, &[-1000.0]),
+            Ok(0)
+        );
+        // Trapping leaves the operand in place.
+        let mut vm = Vm::with_config(config(fp::Overflow::Trapping)).unwrap();
+        vm.data_stack.push(fp::float_to_fix(11.0));
+        assert!(vm.load(&[OpCodes::Exp as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::Overflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.as_slice(), &[fp::float_to_fix(11.0)]);
+    }
+
+    #[test]
+    fn test_domain_fault() {
+        for (op, a) in [
+            (OpCodes::Sqrt as u8, -1.0),
+            (OpCodes::Log as u8, 0.0),
+            (OpCodes::Log as u8, -3.0),
+        ]
+        .iter()
+        {
+            let mut vm = Vm::new();
+            vm.data_stack.push(fp::float_to_fix(*a));
+            assert!(vm.load(&[*op]));
+            assert_eq!(vm.cycle_once(), Err(VmError::Domain));
+            assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+            assert_eq!(vm.data_stack.as_slice(), &[fp::float_to_fix(*a)]);
+        }
+    }
+
+    #[test]
+    fn test_underflow() {
+        let mut vm = Vm::new();
+        vm.data_stack.push(0);
+        assert!(vm.load(&[OpCodes::Atan2 as u8, OpCodes::Sin as u8]));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.data_stack.as_slice(), &[0], "Operand not restored!");
+        vm.data_stack.clear();
+        vm.resume();
+        vm.pc = 1;
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+    }
+
+    #[test]
+    #[allow(clippy::unusual_byte_groupings)]
+    fn test_invalid_math() {
+        for inst in [0x00, 0b000_111_01, OpCodes::Sqrt as u8 | 0b01].iter() {
+            let mut vm = Vm::new();
+            assert!(vm.load(&[*inst]));
+            assert_eq!(vm.cycle_once(), Err(VmError::InvalidOpcode(*inst, 0)));
+            assert_eq!(vm.pc, 0, "PC moved past an invalid instruction!");
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use super::opcodes::*;
+use super::StepOutcome;
+use super::VmError;
+
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<StepOutcome, VmError> {
+    let op_type = MiscOpTypes::from(inst);
+    match op_type {
+        MiscOpTypes::Brk => Ok(StepOutcome::Halted),
+        MiscOpTypes::Nop => Ok(StepOutcome::Retired),
+        _ => Err(super::invalid_opcode(vm, inst)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::Vm;
+    use crate::vm::VmState;
+    use crate::vm::RAM_SIZE;
+
+    #[test]
+    fn test_nop() {
+        let mut vm = Vm::new();
+        let code = [OpCodes::Nop as u8; 1];
+        assert!(vm.load(&code));
+        assert_eq!(vm.cycle_once(), Ok(StepOutcome::Retired));
+        assert_eq!(vm.pc, 1, "Nop failed to increment program counter!");
+        assert!(vm.data_stack.empty(), "Nop modified the data stack!");
+    }
+
+    #[test]
+    fn test_brk() {
+        let mut vm = Vm::new();
+        let code = [OpCodes::Brk as u8; 1];
+        assert!(vm.load(&code));
+        assert_eq!(vm.cycle_once(), Ok(StepOutcome::Halted));
+        assert_eq!(vm.pc, 1, "Brk failed to increment program counter!");
+        assert_eq!(vm.state(), VmState::Halted, "Brk failed to halt the vm!");
+        // Halted vms don't execute until resumed.
+        assert_eq!(vm.cycle_once(), Ok(StepOutcome::Halted));
+        assert_eq!(vm.pc, 1, "Halted vm executed an instruction!");
+        vm.resume();
+        assert_eq!(vm.state(), VmState::Running);
+        vm.pc = RAM_SIZE;
+        assert_eq!(
+            vm.cycle_once(),
+            Err(VmError::MemoryOutOfBounds(RAM_SIZE as isize))
+        );
+        assert_eq!(vm.pc, RAM_SIZE, "PC modified at end of ram!");
+    }
+
+    #[test]
+    fn test_invalid_misc() {
+        let mut vm = Vm::new();
+        let code = [OpCodes::Brk as u8 | 0b10; 1];
+        assert!(vm.load(&code));
+        assert_eq!(vm.cycle_once(), Err(VmError::InvalidOpcode(code[0], 0)));
+        assert_eq!(vm.pc, 0, "PC moved past an invalid instruction!");
+    }
+}
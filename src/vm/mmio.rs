@@ -0,0 +1,141 @@
+//! Host devices mapped into the vm's address space.
+
+use crate::fp::Fix16;
+
+// A host object backing a range of addresses. Loads and stores the guest makes within the range
+// are handed to the device, with the address given as an offset from the start of the range.
+pub trait MmioDevice {
+    // Produces the word the guest loads from an offset.
+    fn read(&mut self, offset: usize) -> Fix16;
+    // Accepts a word the guest stores to an offset.
+    fn write(&mut self, offset: usize, val: Fix16);
+}
+
+// A device and the range of addresses it is mapped to.
+pub(super) struct MappedDevice {
+    pub(super) start: usize,
+    pub(super) end: usize,
+    pub(super) device: Box<dyn MmioDevice>,
+}
+
+impl MappedDevice {
+    pub(super) fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    pub(super) fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fp;
+    use crate::vm::opcodes::*;
+    use crate::vm::Vm;
+    use crate::vm::VmError;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Writes = Rc<RefCell<Vec<(usize, Fix16)>>>;
+
+    // Counts reads and records every write.
+    struct Register {
+        reads: Rc<RefCell<i32>>,
+        writes: Writes,
+    }
+
+    impl MmioDevice for Register {
+        fn read(&mut self, offset: usize) -> Fix16 {
+            *self.reads.borrow_mut() += 1;
+            Fix16::from_bits(offset as i32)
+        }
+
+        fn write(&mut self, offset: usize, val: Fix16) {
+            self.writes.borrow_mut().push((offset, val));
+        }
+    }
+
+    fn init_device() -> (Box<Register>, Rc<RefCell<i32>>, Writes) {
+        let reads = Rc::new(RefCell::new(0));
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let device = Box::new(Register {
+            reads: reads.clone(),
+            writes: writes.clone(),
+        });
+        (device, reads, writes)
+    }
+
+    #[test]
+    fn test_map_device() {
+        let mut vm = Vm::new();
+        let (device, _, _) = init_device();
+        assert!(vm.map_device(0x100, 0x10, device));
+        let (device, _, _) = init_device();
+        assert!(!vm.map_device(0x10c, 0x10, device), "Mapped over a device!");
+        let (device, _, _) = init_device();
+        assert!(!vm.map_device(0x7ffe, 4, device), "Mapped past end of ram!");
+        let (device, _, _) = init_device();
+        assert!(!vm.map_device(0x200, 0, device), "Mapped an empty range!");
+        assert!(vm.unmap_device(0x10f).is_some());
+        assert!(vm.unmap_device(0x100).is_none());
+        let (device, _, _) = init_device();
+        assert!(vm.map_device(0x10c, 0x10, device));
+    }
+
+    #[test]
+    fn test_device_load_store() {
+        let mut vm = Vm::new();
+        let (device, reads, writes) = init_device();
+        assert!(vm.map_device(0x100, 0x10, device));
+        let mut code = [0u8; 8];
+        code[0] = OpCodes::StoreStk as u8;
+        code[1] = OpCodes::PushStk as u8;
+        code[2] = OpCodes::PushIndImm as u8;
+        code[3..5].clone_from_slice(&8i16.to_le_bytes());
+        assert!(vm.load(&code));
+        vm.data_stack.push(1234);
+        vm.data_stack.push(fp::float_to_fix(0x104 as f32));
+        vm.cycle_once().unwrap();
+        assert_eq!(
+            *writes.borrow(),
+            vec![(4, Fix16::from_bits(1234))],
+            "Store missed the device!"
+        );
+        assert_eq!(vm.read_i32(0x104), Ok(0), "Store wrote through to ram!");
+        vm.data_stack.push(fp::float_to_fix(0x10c as f32));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.data_stack.pop(), Some(0xc), "Push missed the device!");
+        vm.data_stack.push(fp::float_to_fix(0x100 as f32));
+        vm.cycle_once().unwrap();
+        assert_eq!(
+            vm.data_stack.pop(),
+            Some(8),
+            "Indexed push missed the device!"
+        );
+        assert_eq!(*reads.borrow(), 2);
+    }
+
+    #[test]
+    fn test_device_straddle() {
+        let mut vm = Vm::new();
+        let (device, reads, writes) = init_device();
+        assert!(vm.map_device(0x100, 0x10, device));
+        let code = [OpCodes::StoreStk as u8, OpCodes::PushStk as u8];
+        assert!(vm.load(&code));
+        let addr = fp::float_to_fix(0x10e as f32);
+        vm.data_stack.push(1);
+        vm.data_stack.push(addr);
+        assert_eq!(vm.cycle_once(), Err(VmError::ProtectionFault(0x10e)));
+        assert_eq!(vm.data_stack.pop(), Some(addr), "Address not restored!");
+        vm.resume();
+        vm.pc = 1;
+        let addr = fp::float_to_fix(0xfe as f32);
+        vm.data_stack.push(addr);
+        assert_eq!(vm.cycle_once(), Err(VmError::ProtectionFault(0xfe)));
+        assert_eq!(vm.data_stack.pop(), Some(addr), "Address not restored!");
+        assert_eq!(*reads.borrow(), 0);
+        assert!(writes.borrow().is_empty());
+    }
+}
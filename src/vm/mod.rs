@@ -1,47 +1,216 @@
-/// Module with the central vm structures.
+//! Module with the central vm structures.
+mod accumulator_op_impl;
 mod arithmetic_op_impl;
 mod bit_op_impl;
+mod compare_op_impl;
+mod config;
+mod control_op_impl;
+mod interrupt_op_impl;
+mod math_op_impl;
+mod misc_op_impl;
+mod mmio;
+mod opcodes;
 mod port_op_impl;
 mod stack_op_impl;
-mod opcodes;
 
 use crate::fp;
+use crate::fp::Fix16;
 use crate::stk::Stack;
+pub use config::VmConfig;
+use mmio::MappedDevice;
+pub use mmio::MmioDevice;
 use opcodes::*;
+use std::convert::TryFrom;
+use std::fmt;
 
+// Defaults for Vm::new, which are also the most the ISA can address.
 const RAM_SIZE: usize = 1 << 15;
-const NUM_INTERRUPTS: usize = 8;
-const NUM_PORTS: usize = NUM_INTERRUPTS;
+const NUM_PORTS: usize = 8;
+const NUM_FAULT_VECTORS: usize = 8;
 const INVALID_INTERRUPT: i16 = -1;
 
+// Result of successfully executing a single instruction.
+#[derive(Debug, PartialEq)]
+pub enum StepOutcome {
+    Retired,
+    Halted,
+    // The instruction faulted and the guest's fault handler was entered.
+    Trapped(VmError),
+    // The instruction is blocked reading an empty port and will be retried.
+    Waiting(usize),
+}
+
+// What the vm is doing between instructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VmState {
+    Running,
+    // Stopped by BRK until the host resumes it.
+    Halted,
+    // Stopped by a fault the guest didn't handle until the host resumes it.
+    Faulted(VmError),
+    // Blocked on a PortPop or PortGet until the host pushes onto the port.
+    WaitingForPort(usize),
+    // Running a port interrupt handler.
+    InInterrupt,
+}
+
+// Faults raised by a guest program. When cycle_once returns one of these the pc is left on the
+// faulting instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VmError {
+    StackUnderflow,
+    StackOverflow,
+    // The offending byte and the address it was fetched from.
+    InvalidOpcode(u8, usize),
+    MemoryOutOfBounds(isize),
+    DivideByZero,
+    // A write to ROM or an instruction fetch from a no-execute region, with the address.
+    ProtectionFault(usize),
+    // An arithmetic result too large for 16.16 under the Trapping overflow policy.
+    Overflow,
+    // A math function given an operand outside its domain, such as the square root of -1.
+    Domain,
+}
+
+impl VmError {
+    // The code pushed for guest fault handlers, which also selects the fault vector.
+    pub fn code(&self) -> u8 {
+        match self {
+            VmError::InvalidOpcode(_, _) => 0,
+            VmError::StackUnderflow => 1,
+            VmError::StackOverflow => 2,
+            VmError::MemoryOutOfBounds(_) => 3,
+            VmError::DivideByZero => 4,
+            VmError::ProtectionFault(_) => 5,
+            VmError::Overflow => 6,
+            VmError::Domain => 7,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::StackUnderflow => write!(f, "stack underflow"),
+            VmError::StackOverflow => write!(f, "stack overflow"),
+            VmError::InvalidOpcode(op, pc) => {
+                write!(f, "invalid opcode {:#04x} at {:#06x}", op, pc)
+            }
+            VmError::MemoryOutOfBounds(addr) => {
+                write!(f, "memory access out of bounds at {}", addr)
+            }
+            VmError::DivideByZero => write!(f, "divide by zero"),
+            VmError::ProtectionFault(addr) => {
+                write!(f, "protection fault at {:#06x}", addr)
+            }
+            VmError::Overflow => write!(f, "arithmetic overflow"),
+            VmError::Domain => write!(f, "operand outside the function's domain"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+// How a region of ram may be accessed by the guest. Ram not covered by a region behaves as Ram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionKind {
+    // Readable and executable, but not writable.
+    Rom,
+    // Readable, writable and executable.
+    Ram,
+    // Readable and writable, but not executable.
+    NoExec,
+}
+
+// A range of ram declared by the embedder.
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    start: usize,
+    end: usize,
+    kind: RegionKind,
+}
+
+// The kinds of access checked against the region map.
+#[derive(Clone, Copy, PartialEq)]
+enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+// Why a call to Vm::run returned.
+#[derive(Debug, PartialEq)]
+pub enum StopReason {
+    Halted,
+    BudgetExhausted,
+    Faulted(VmError),
+    WaitingForPort(usize),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RunResult {
+    pub reason: StopReason,
+    pub retired: usize,
+}
+
 pub struct Vm {
+    config: VmConfig,
     ram: Box<[u8]>,
     pc: usize,
+    state: VmState,
     data_stack: Box<Stack>,
     call_stack: Box<Stack>,
+    // The 32.32 multiply-accumulate register.
+    accumulator: i64,
+    // Port interrupt vectors come first, followed by one vector per fault code.
     interrupts: Box<[i16]>,
+    interrupts_enabled: bool,
+    // Number of port interrupt handlers entered and not yet returned from.
+    interrupt_depth: usize,
+    pending_interrupts: Box<[bool]>,
     ports: Box<[Stack]>,
+    // Later regions take precedence over earlier ones where they overlap.
+    regions: Vec<Region>,
+    // Devices never overlap one another.
+    devices: Vec<MappedDevice>,
 }
 
 impl Vm {
     pub fn new() -> Box<Vm> {
-        let ram = vec![0; RAM_SIZE];
-        let interrupts = vec![INVALID_INTERRUPT; NUM_INTERRUPTS];
-        let stk_basis = Stack::new();
-        let mut ports = Vec::<Stack>::with_capacity(NUM_PORTS);
-        for _i in 0..NUM_PORTS {
-            ports.push(stk_basis);
-        }
-        let data_stack = Box::new(stk_basis);
+        Vm::with_config(VmConfig::default()).unwrap()
+    }
+
+    // Builds a vm sized by the config. Returns None if the config is not valid.
+    pub fn with_config(config: VmConfig) -> Option<Box<Vm>> {
+        if !config.is_valid() {
+            return None;
+        }
+        let ram = vec![0; config.ram_size];
+        let interrupts = vec![INVALID_INTERRUPT; config.num_ports + NUM_FAULT_VECTORS];
+        let pending_interrupts = vec![false; config.num_ports];
+        let stk_basis = Stack::with_depth(config.stack_depth);
+        let mut ports = Vec::<Stack>::with_capacity(config.num_ports);
+        for _i in 0..config.num_ports {
+            ports.push(stk_basis.clone());
+        }
+        let data_stack = Box::new(stk_basis.clone());
         let call_stack = Box::new(stk_basis);
-        Box::new(Vm {
+        Some(Box::new(Vm {
+            config,
             ram: ram.into_boxed_slice(),
             pc: 0,
+            state: VmState::Running,
             data_stack,
             call_stack,
+            accumulator: 0,
             interrupts: interrupts.into_boxed_slice(),
+            interrupts_enabled: true,
+            interrupt_depth: 0,
+            pending_interrupts: pending_interrupts.into_boxed_slice(),
             ports: ports.into_boxed_slice(),
-        })
+            regions: Vec::new(),
+            devices: Vec::new(),
+        }))
     }
 
     pub fn load(&mut self, code_in: &[u8]) -> bool {
@@ -52,119 +221,526 @@ impl Vm {
         true
     }
 
-    pub fn cycle_once(&mut self) {
-        // Grab the next instruction.
-        if self.pc >= RAM_SIZE {
-            return;
+    // The contents of RAM, suitable for saving as a program image and loading again.
+    pub fn image(&self) -> &[u8] {
+        &self.ram
+    }
+
+    // Reads the byte at an address.
+    pub fn read_u8(&self, addr: usize) -> Result<u8, VmError> {
+        Ok(self.ram[self.mem_range(addr, 1)?.start])
+    }
+
+    // Reads the little-endian 16 bit word at an address.
+    pub fn read_i16(&self, addr: usize) -> Result<i16, VmError> {
+        let mut bytes: [u8; 2] = [0; 2];
+        bytes.clone_from_slice(&self.ram[self.mem_range(addr, 2)?]);
+        Ok(i16::from_le_bytes(bytes))
+    }
+
+    // Reads the little-endian 32 bit word at an address.
+    pub fn read_i32(&self, addr: usize) -> Result<i32, VmError> {
+        let mut bytes: [u8; 4] = [0; 4];
+        bytes.clone_from_slice(&self.ram[self.mem_range(addr, 4)?]);
+        Ok(i32::from_le_bytes(bytes))
+    }
+
+    // Reads the little-endian 16.16 word at an address.
+    pub fn read_fix(&self, addr: usize) -> Result<Fix16, VmError> {
+        self.read_i32(addr).map(Fix16::from_bits)
+    }
+
+    // Writes the byte at an address.
+    pub fn write_u8(&mut self, addr: usize, val: u8) -> Result<(), VmError> {
+        let range = self.mem_range(addr, 1)?;
+        self.ram[range.start] = val;
+        Ok(())
+    }
+
+    // Writes a little-endian 16 bit word at an address.
+    pub fn write_i16(&mut self, addr: usize, val: i16) -> Result<(), VmError> {
+        let range = self.mem_range(addr, 2)?;
+        self.ram[range].clone_from_slice(&val.to_le_bytes());
+        Ok(())
+    }
+
+    // Writes a little-endian 32 bit word at an address.
+    pub fn write_i32(&mut self, addr: usize, val: i32) -> Result<(), VmError> {
+        let range = self.mem_range(addr, 4)?;
+        self.ram[range].clone_from_slice(&val.to_le_bytes());
+        Ok(())
+    }
+
+    // Writes a little-endian 16.16 word at an address.
+    pub fn write_fix(&mut self, addr: usize, val: Fix16) -> Result<(), VmError> {
+        self.write_i32(addr, val.to_bits())
+    }
+
+    // Declares how the guest may access len bytes of ram starting at an address, overriding any
+    // earlier region they overlap. Returns false if the range lies outside of ram.
+    pub fn map_region(&mut self, start: usize, len: usize, kind: RegionKind) -> bool {
+        match self.mem_range(start, len) {
+            Ok(range) => {
+                self.regions.push(Region {
+                    start: range.start,
+                    end: range.end,
+                    kind,
+                });
+                true
+            }
+            Err(_) => false,
         }
-        let next_inst = self.ram[self.pc];
-        self.pc += 1;
+    }
+
+    // Removes every region, leaving all of ram readable, writable and executable.
+    pub fn clear_regions(&mut self) {
+        self.regions.clear();
+    }
+
+    // How the guest may access the byte at an address.
+    pub fn region_kind(&self, addr: usize) -> RegionKind {
+        self.regions
+            .iter()
+            .rev()
+            .find(|r| r.start <= addr && addr < r.end)
+            .map_or(RegionKind::Ram, |r| r.kind)
+    }
+
+    // Maps a device over len bytes of ram starting at an address. Guest loads and stores there
+    // go to the device instead of ram. Returns false if the range lies outside of ram or overlaps
+    // another device.
+    pub fn map_device(&mut self, start: usize, len: usize, device: Box<dyn MmioDevice>) -> bool {
+        let range = match self.mem_range(start, len) {
+            Ok(range) => range,
+            Err(_) => return false,
+        };
+        if len == 0
+            || self
+                .devices
+                .iter()
+                .any(|d| d.overlaps(range.start, range.end))
+        {
+            return false;
+        }
+        self.devices.push(MappedDevice {
+            start: range.start,
+            end: range.end,
+            device,
+        });
+        true
+    }
+
+    // Removes the device mapped at an address, handing it back to the host.
+    pub fn unmap_device(&mut self, addr: usize) -> Option<Box<dyn MmioDevice>> {
+        let index = self.devices.iter().position(|d| d.contains(addr))?;
+        Some(self.devices.remove(index).device)
+    }
+
+    // Loads the word the guest sees at an address, from a device if one is mapped there.
+    fn load_word(&mut self, addr: usize) -> Result<i32, VmError> {
+        match self.devices.iter_mut().find(|d| d.contains(addr)) {
+            Some(d) => Ok(d.device.read(addr - d.start).to_bits()),
+            None => self.read_i32(addr),
+        }
+    }
+
+    // Stores a word where the guest sees it, to a device if one is mapped there.
+    fn store_word(&mut self, addr: usize, val: i32) -> Result<(), VmError> {
+        match self.devices.iter_mut().find(|d| d.contains(addr)) {
+            Some(d) => {
+                d.device.write(addr - d.start, Fix16::from_bits(val));
+                Ok(())
+            }
+            None => self.write_i32(addr, val),
+        }
+    }
+
+    // Checks that the guest may access the len bytes at an address in the given way. Data accesses
+    // touching a device must lie entirely within it.
+    fn check_access(&self, addr: usize, len: usize, access: Access) -> Result<(), VmError> {
+        let range = self.mem_range(addr, len)?;
+        if access != Access::Execute {
+            let straddles = |d: &MappedDevice| {
+                d.overlaps(range.start, range.end) && !(d.start <= addr && range.end <= d.end)
+            };
+            if self.devices.iter().any(straddles) {
+                return Err(VmError::ProtectionFault(addr));
+            }
+        }
+        let allowed = |kind| match access {
+            Access::Read => true,
+            Access::Write => kind != RegionKind::Rom,
+            Access::Execute => kind != RegionKind::NoExec,
+        };
+        match range.into_iter().find(|a| !allowed(self.region_kind(*a))) {
+            Some(a) => Err(VmError::ProtectionFault(a)),
+            None => Ok(()),
+        }
+    }
+
+    // The range of ram covered by len bytes at an address, or a fault if any of it is outside.
+    fn mem_range(&self, addr: usize, len: usize) -> Result<std::ops::Range<usize>, VmError> {
+        match addr.checked_add(len) {
+            Some(end) if end <= self.ram.len() => Ok(addr..end),
+            _ => Err(VmError::MemoryOutOfBounds(addr as isize)),
+        }
+    }
+
+    // Pushes a value from the host onto a port, raising that port's interrupt if a handler is set.
+    pub fn port_push(&mut self, port: usize, val: Fix16) -> bool {
+        if port >= self.ports.len() || !self.ports[port].push(val.to_bits()) {
+            return false;
+        }
+        if self.interrupts[port] != INVALID_INTERRUPT {
+            self.pending_interrupts[port] = true;
+        }
+        true
+    }
+
+    // Pops a value the guest has pushed onto a port.
+    pub fn port_pop(&mut self, port: usize) -> Option<Fix16> {
+        if port >= self.ports.len() {
+            return None;
+        }
+        self.ports[port].pop().map(Fix16::from_bits)
+    }
+
+    // Index into interrupts of the vector for a fault code.
+    fn fault_vector(&self, code: u8) -> usize {
+        self.ports.len() + code as usize
+    }
+
+    pub fn data_stack(&self) -> &Stack {
+        &self.data_stack
+    }
+
+    pub fn call_stack(&self) -> &Stack {
+        &self.call_stack
+    }
+
+    // The data stack's values as 16.16, from the top down.
+    pub fn data_stack_fix(&self) -> impl Iterator<Item = Fix16> + '_ {
+        self.data_stack.iter().map(Fix16::from_bits)
+    }
+
+    // The call stack's values as 16.16, from the top down.
+    pub fn call_stack_fix(&self) -> impl Iterator<Item = Fix16> + '_ {
+        self.call_stack.iter().map(Fix16::from_bits)
+    }
+
+    // The multiply-accumulate register, as 32.32 fixed point.
+    pub fn accumulator(&self) -> i64 {
+        self.accumulator
+    }
+
+    // The stack behind a port, or None if the port doesn't exist.
+    pub fn port(&self, port: usize) -> Option<&Stack> {
+        self.ports.get(port)
+    }
+
+    // A port's values as 16.16 from the top down, or None if the port doesn't exist.
+    pub fn port_fix(&self, port: usize) -> Option<impl Iterator<Item = Fix16> + '_> {
+        self.ports.get(port).map(|p| p.iter().map(Fix16::from_bits))
+    }
+
+    pub fn state(&self) -> VmState {
+        self.state
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    // Clears a Halted or Faulted state so execution continues at the pc. After a fault the
+    // faulting instruction is retried.
+    pub fn resume(&mut self) {
+        if let VmState::Halted | VmState::Faulted(_) = self.state {
+            self.state = self.active_state();
+        }
+    }
+
+    // The state to be in while executing normally.
+    fn active_state(&self) -> VmState {
+        if self.interrupt_depth > 0 {
+            VmState::InInterrupt
+        } else {
+            VmState::Running
+        }
+    }
+
+    // Executes up to max_steps instructions, stopping early on a BRK, a fault the guest doesn't
+    // handle, or a read from an empty port. Instructions that trap into a fault handler use up a
+    // step but are not retired.
+    pub fn run(&mut self, max_steps: usize) -> RunResult {
+        let mut retired = 0;
+        if let VmState::Halted = self.state {
+            return RunResult {
+                reason: StopReason::Halted,
+                retired,
+            };
+        }
+        for _step in 0..max_steps {
+            match self.cycle_once() {
+                Ok(StepOutcome::Retired) => retired += 1,
+                Ok(StepOutcome::Trapped(_)) => {}
+                Ok(StepOutcome::Halted) => {
+                    return RunResult {
+                        reason: StopReason::Halted,
+                        retired: retired + 1,
+                    }
+                }
+                Ok(StepOutcome::Waiting(port)) => {
+                    return RunResult {
+                        reason: StopReason::WaitingForPort(port),
+                        retired,
+                    }
+                }
+                Err(e) => {
+                    return RunResult {
+                        reason: StopReason::Faulted(e),
+                        retired,
+                    }
+                }
+            }
+        }
+        RunResult {
+            reason: StopReason::BudgetExhausted,
+            retired,
+        }
+    }
+
+    // Executes a single instruction, servicing pending interrupts first. Does nothing while the
+    // vm is halted or faulted.
+    pub fn cycle_once(&mut self) -> Result<StepOutcome, VmError> {
+        match self.state {
+            VmState::Halted => return Ok(StepOutcome::Halted),
+            VmState::Faulted(e) => return Err(e),
+            _ => {}
+        }
+        if let Err(e) = interrupt_op_impl::service(self) {
+            self.state = VmState::Faulted(e);
+            return Err(e);
+        }
+        if let VmState::WaitingForPort(port) = self.state {
+            if self.ports[port].empty() {
+                return Ok(StepOutcome::Waiting(port));
+            }
+            self.state = self.active_state();
+        }
+        let inst_pc = self.pc;
+        match self.execute() {
+            Ok(StepOutcome::Halted) => {
+                self.state = VmState::Halted;
+                Ok(StepOutcome::Halted)
+            }
+            Err(e) => {
+                self.pc = inst_pc;
+                if interrupt_op_impl::trap(self, e) {
+                    Ok(StepOutcome::Trapped(e))
+                } else {
+                    self.state = VmState::Faulted(e);
+                    Err(e)
+                }
+            }
+            result => result,
+        }
+    }
+
+    fn execute(&mut self) -> Result<StepOutcome, VmError> {
+        // Grab the next instruction.
+        let next_inst = self.fetch()?;
         // Figure out which group it belongs to.
         let fam: OpFamily = OpFamily::from(next_inst);
         match fam {
-            OpFamily::StackOp => stack_op_impl::cycle_op(self, next_inst),
-            OpFamily::ArithmeticOp => arithmetic_op_impl::cycle_op(self, next_inst),
-            OpFamily::BitManipOp => bit_op_impl::cycle_op(self, next_inst),
-            OpFamily::PortOp => port_op_impl::cycle_op(self, next_inst),
-            _ => {}
+            OpFamily::StackOp => stack_op_impl::cycle_op(self, next_inst)?,
+            OpFamily::ArithmeticOp => arithmetic_op_impl::cycle_op(self, next_inst)?,
+            OpFamily::BitManipOp => bit_op_impl::cycle_op(self, next_inst)?,
+            OpFamily::PortOp => port_op_impl::cycle_op(self, next_inst)?,
+            OpFamily::ControlOp => control_op_impl::cycle_op(self, next_inst)?,
+            OpFamily::InterruptOp => interrupt_op_impl::cycle_op(self, next_inst)?,
+            OpFamily::MiscOp => return misc_op_impl::cycle_op(self, next_inst),
+            OpFamily::MathOp => math_op_impl::cycle_op(self, next_inst)?,
+            OpFamily::Extended => self.execute_extended()?,
+            _ => return Err(invalid_opcode(self, next_inst)),
+        }
+        if let VmState::WaitingForPort(port) = self.state {
+            return Ok(StepOutcome::Waiting(port));
+        }
+        Ok(StepOutcome::Retired)
+    }
+
+    // Decodes the second byte of an instruction behind the Ext prefix.
+    fn execute_extended(&mut self) -> Result<(), VmError> {
+        let ext_inst = self.fetch()?;
+        match ExtOpFamily::from(ext_inst) {
+            ExtOpFamily::ArithmeticOp => arithmetic_op_impl::cycle_ext_op(self, ext_inst),
+            ExtOpFamily::CompareOp => compare_op_impl::cycle_op(self, ext_inst),
+            ExtOpFamily::IntegerOp => arithmetic_op_impl::cycle_int_op(self, ext_inst),
+            ExtOpFamily::AccumulatorOp => accumulator_op_impl::cycle_op(self, ext_inst),
+            ExtOpFamily::Invalid => Err(invalid_opcode(self, ext_inst)),
         }
     }
+
+    // Reads the instruction byte at the pc and moves past it.
+    fn fetch(&mut self) -> Result<u8, VmError> {
+        self.check_access(self.pc, 1, Access::Execute)?;
+        let inst = self.read_u8(self.pc)?;
+        self.pc += 1;
+        Ok(inst)
+    }
+
+    // Reads the 16 bit operand at the pc, which is fetched like the opcode before it.
+    fn operand_i16(&self) -> Result<i16, VmError> {
+        self.check_access(self.pc, 2, Access::Execute)?;
+        self.read_i16(self.pc)
+    }
+
+    // Reads the 32 bit operand at the pc, which is fetched like the opcode before it.
+    fn operand_i32(&self) -> Result<i32, VmError> {
+        self.check_access(self.pc, 4, Access::Execute)?;
+        self.read_i32(self.pc)
+    }
+}
+
+// Blocks on an empty port, leaving the pc on the current instruction so it is retried.
+fn wait_for_port(vm: &mut Vm, port: usize) {
+    vm.pc -= 1;
+    vm.state = VmState::WaitingForPort(port);
+}
+
+// Builds the fault for an opcode that doesn't decode. Expects the pc to have moved past it.
+fn invalid_opcode(vm: &Vm, inst: u8) -> VmError {
+    VmError::InvalidOpcode(inst, vm.pc - 1)
+}
+
+// Faults unless the stack holds at least n values, so ops can check before consuming anything.
+fn require(stk: &Stack, n: usize) -> Result<(), VmError> {
+    if stk.len() < n {
+        Err(VmError::StackUnderflow)
+    } else {
+        Ok(())
+    }
 }
 
-// Extracts the adress needed for an op given the adressing mode. Increments the program counter.
-fn get_addr(vm: &mut Vm, addr_mode: &OpAddrMode) -> Option<isize> {
+fn pop(stk: &mut Stack) -> Result<i32, VmError> {
+    stk.pop().ok_or(VmError::StackUnderflow)
+}
+
+fn push(stk: &mut Stack, val: i32) -> Result<(), VmError> {
+    if stk.push(val) {
+        Ok(())
+    } else {
+        Err(VmError::StackOverflow)
+    }
+}
+
+// Checks that an address lies within ram.
+fn check_addr(vm: &Vm, addr: isize) -> Result<isize, VmError> {
+    check_range(vm, addr, 1)
+}
+
+// Checks that the len bytes starting at an address lie within ram.
+fn check_range(vm: &Vm, addr: isize, len: usize) -> Result<isize, VmError> {
+    if addr >= 0 && addr as usize + len <= vm.ram.len() {
+        Ok(addr)
+    } else {
+        Err(VmError::MemoryOutOfBounds(addr))
+    }
+}
+
+// Checks that the len bytes at an address lie within ram and permit the access.
+fn check_addr_access(vm: &Vm, addr: isize, len: usize, access: Access) -> Result<isize, VmError> {
+    let addr = check_range(vm, addr, len)?;
+    vm.check_access(addr as usize, len, access)?;
+    Ok(addr)
+}
+
+// Converts a code address to the 16.16 form return addresses are kept in. Addresses past the
+// largest 16.16 integer fault instead of wrapping negative.
+fn return_addr(pc: usize) -> Result<i32, VmError> {
+    i32::try_from(pc)
+        .ok()
+        .and_then(|pc| pc.checked_mul(1 << 16))
+        .ok_or(VmError::MemoryOutOfBounds(pc as isize))
+}
+
+// Pops a 16.16 return address off of the call stack, leaving it there if it is out of range.
+fn pop_return_addr(vm: &mut Vm) -> Result<usize, VmError> {
+    let ret_addr = pop(&mut vm.call_stack)?;
+    match check_addr(vm, (ret_addr >> 16) as isize) {
+        Ok(addr) => Ok(addr as usize),
+        Err(e) => {
+            vm.call_stack.push(ret_addr);
+            Err(e)
+        }
+    }
+}
+
+// Extracts the adress needed for an op given the adressing mode, checking that the len bytes
+// there lie within ram and permit the access. Increments the program counter. A stack value
+// consumed for an address that turns out to be unusable is pushed back.
+fn get_addr(
+    vm: &mut Vm,
+    addr_mode: &OpAddrMode,
+    len: usize,
+    access: Access,
+) -> Result<isize, VmError> {
     match addr_mode {
         OpAddrMode::Immediate => {
-            if vm.pc + 4 >= RAM_SIZE {
-                return None;
-            }
-            let mut val_arr: [u8; 4] = [0; 4];
-            val_arr[0..4].clone_from_slice(&vm.ram[vm.pc as usize..vm.pc as usize + 4]);
-            let val = (i32::from_ne_bytes(val_arr) >> 16) as isize;
+            let val = (vm.operand_i32()? >> 16) as isize;
             vm.pc += 4;
-            Some(val)
+            check_addr_access(vm, val, len, access)
         }
         OpAddrMode::IndexStack => {
-            if vm.pc + 2 >= RAM_SIZE {
-                return None;
-            }
-            let mut arg: Option<isize> = None;
-            let mut base_arr: [u8; 2] = [0; 2];
-            base_arr[0..2].clone_from_slice(&vm.ram[vm.pc as usize..vm.pc as usize + 2]);
-            let base = i16::from_ne_bytes(base_arr) as isize;
-            if let Some(offset) = vm.data_stack.pop() {
-                let off = offset >> 16;
-                let val_addr: isize = base + off as isize;
-                if val_addr > 0 && val_addr < RAM_SIZE as isize {
-                    arg = Some(val_addr);
-                }
-            }
+            let base = vm.operand_i16()? as isize;
+            let offset = pop(&mut vm.data_stack)?;
+            let val_addr: isize = base + (offset >> 16) as isize;
             vm.pc += 2;
-            arg
+            check_addr_access(vm, val_addr, len, access).inspect_err(|_| {
+                vm.data_stack.push(offset);
+            })
         }
         OpAddrMode::IndexImmediate => {
-            if vm.pc + 2 >= RAM_SIZE {
-                return None;
-            }
-            let mut arg: Option<isize> = None;
-            let mut offset_arr: [u8; 2] = [0; 2];
-            offset_arr[0..2].clone_from_slice(&vm.ram[vm.pc as usize..vm.pc as usize + 2]);
-            let offset = i16::from_ne_bytes(offset_arr) as isize;
-            if let Some(base) = vm.data_stack.pop() {
-                let base = base >> 16;
-                let val_addr: isize = base as isize + offset;
-                if val_addr > 0 && val_addr < RAM_SIZE as isize {
-                    arg = Some(val_addr);
-                }
-            }
+            let offset = vm.operand_i16()? as isize;
+            let base = pop(&mut vm.data_stack)?;
+            let val_addr: isize = (base >> 16) as isize + offset;
             vm.pc += 2;
-            arg
+            check_addr_access(vm, val_addr, len, access).inspect_err(|_| {
+                vm.data_stack.push(base);
+            })
         }
         OpAddrMode::Stack => {
-            let mut arg: Option<isize> = None;
-            if let Some(addr) = vm.data_stack.pop() {
-                arg = Some((addr >> 16) as isize);
-            }
-            arg
+            let addr = pop(&mut vm.data_stack)?;
+            check_addr_access(vm, (addr >> 16) as isize, len, access).inspect_err(|_| {
+                vm.data_stack.push(addr);
+            })
         }
-        _ => None,
+        _ => Err(invalid_opcode(vm, vm.ram[vm.pc - 1])),
     }
 }
 
 // Extracts the value in memory or on the stack based on the addressing mode. Increments the program counter if necessary and get_addr does not (or isn't used).
 // Used in *_op_impl modules.
-fn get_addr_val(vm: &mut Vm, addr_mode: &OpAddrMode) -> Option<i32> {
+fn get_addr_val(vm: &mut Vm, addr_mode: &OpAddrMode) -> Result<i32, VmError> {
     match addr_mode {
         OpAddrMode::Immediate => {
-            if vm.pc + 4 >= RAM_SIZE {
-                return None;
-            }
-            let mut val_arr: [u8; 4] = [0; 4];
-            val_arr[0..4].clone_from_slice(&vm.ram[vm.pc as usize..vm.pc as usize + 4]);
-            let val = i32::from_ne_bytes(val_arr);
+            let val = vm.operand_i32()?;
             vm.pc += 4;
-            Some(val)
+            Ok(val)
         }
         OpAddrMode::IndexStack | OpAddrMode::IndexImmediate => {
-            if vm.pc + 2 >= RAM_SIZE {
-                return None;
-            }
-            let mut arg: Option<i32> = None;
-            if let Some(addr) = get_addr(vm, addr_mode) {
-                let mut val_arr: [u8; 4] = [0; 4];
-                val_arr[0..4].clone_from_slice(&vm.ram[addr as usize..addr as usize + 4]);
-                arg = Some(i32::from_ne_bytes(val_arr));
-            }
-            arg
+            let addr = get_addr(vm, addr_mode, 4, Access::Read)?;
+            vm.load_word(addr as usize)
         }
-        OpAddrMode::Stack => vm.data_stack.pop(),
-        _ => None,
+        OpAddrMode::Stack => pop(&mut vm.data_stack),
+        _ => Err(invalid_opcode(vm, vm.ram[vm.pc - 1])),
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::fp;
     use crate::vm::Vm;
     use crate::vm::INVALID_INTERRUPT;
     use crate::vm::RAM_SIZE;
@@ -205,4 +781,388 @@ mod test {
         let code: [u8; RAM_SIZE + 10] = [TEST_VAL; RAM_SIZE + 10];
         assert!(!vm.load(&code));
     }
+
+    #[test]
+    fn test_run() {
+        let mut vm = init_vm();
+        let mut code = [OpCodes::Nop as u8; RAM_SIZE];
+        code[10] = OpCodes::Brk as u8;
+        assert!(vm.load(&code));
+        let result = vm.run(100);
+        assert_eq!(
+            result.reason,
+            StopReason::Halted,
+            "Run did not stop at BRK!"
+        );
+        assert_eq!(
+            result.retired, 11,
+            "Run retired the wrong number of instructions!"
+        );
+        assert_eq!(vm.pc, 11);
+        // Halted until the host resumes it.
+        let result = vm.run(5);
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.retired, 0);
+        assert_eq!(vm.pc, 11);
+        vm.resume();
+        let result = vm.run(5);
+        assert_eq!(result.reason, StopReason::BudgetExhausted);
+        assert_eq!(result.retired, 5);
+        assert_eq!(vm.pc, 16);
+        let result = vm.run(0);
+        assert_eq!(result.reason, StopReason::BudgetExhausted);
+        assert_eq!(result.retired, 0);
+        assert_eq!(vm.pc, 16);
+    }
+
+    #[test]
+    fn test_image_byte_order() {
+        // PUSH 1.0; STORE 0x20; PUSH [stk + 0x2f]; BRK, spelled out byte by byte.
+        let mut image = [0u8; 0x34];
+        let code = [
+            OpCodes::PushImm as u8,
+            0x00,
+            0x00,
+            0x01,
+            0x00,
+            OpCodes::StoreImm as u8,
+            0x00,
+            0x00,
+            0x20,
+            0x00,
+            OpCodes::PushIndImm as u8,
+            0x2f,
+            0x00,
+            OpCodes::Brk as u8,
+        ];
+        image[0..code.len()].clone_from_slice(&code);
+        image[0x30..0x34].clone_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+        let mut vm = init_vm();
+        assert!(vm.load(&image));
+        vm.data_stack.push(fp::float_to_fix(1.0));
+        assert_eq!(vm.run(10).reason, StopReason::Halted);
+        assert_eq!(
+            vm.image()[0x20..0x24],
+            [0x00, 0x00, 0x01, 0x00],
+            "Store wrote the wrong byte order!"
+        );
+        assert_eq!(
+            vm.data_stack.pop(),
+            Some(0x12345678),
+            "Indexed push read the wrong bytes!"
+        );
+    }
+
+    #[test]
+    fn test_image_round_trip() {
+        let mut vm = init_vm();
+        let mut code = [0u8; 16];
+        code[0] = OpCodes::StoreImm as u8;
+        code[1..5].clone_from_slice(&fp::float_to_fix(0x40 as f32).to_le_bytes());
+        code[5] = OpCodes::Brk as u8;
+        assert!(vm.load(&code));
+        vm.data_stack.push(fp::float_to_fix(-2.5));
+        assert_eq!(vm.run(10).reason, StopReason::Halted);
+        // A new vm loaded from the saved image sees the stored word.
+        let image = vm.image().to_vec();
+        let mut vm = init_vm();
+        assert!(vm.load(&image));
+        assert_eq!(image, vm.image(), "Loading changed the image!");
+        vm.pc = 0x100;
+        vm.ram[0x100] = OpCodes::PushImm as u8;
+        vm.ram[0x101..0x105].clone_from_slice(&fp::float_to_fix(0x40 as f32).to_le_bytes());
+        vm.ram[0x105] = OpCodes::PushStk as u8;
+        vm.cycle_once().unwrap();
+        vm.cycle_once().unwrap();
+        assert_eq!(
+            vm.data_stack.pop(),
+            Some(fp::float_to_fix(-2.5)),
+            "Image did not round trip!"
+        );
+    }
+
+    #[test]
+    fn test_memory_access() {
+        let mut vm = init_vm();
+        assert_eq!(vm.write_i32(0x10, -2), Ok(()));
+        assert_eq!(vm.read_i32(0x10), Ok(-2));
+        assert_eq!(vm.read_i16(0x12), Ok(-1));
+        assert_eq!(vm.write_fix(0x14, Fix16::from_f32(-1.5)), Ok(()));
+        assert_eq!(vm.read_i32(0x14), Ok(fp::float_to_fix(-1.5)));
+        assert_eq!(vm.read_fix(0x14), Ok(Fix16::from_f32(-1.5)));
+        assert_eq!(vm.write_i16(0x20, 0x1234), Ok(()));
+        assert_eq!(vm.read_u8(0x20), Ok(0x34));
+        assert_eq!(vm.write_u8(RAM_SIZE - 1, 7), Ok(()));
+        assert_eq!(vm.read_u8(RAM_SIZE - 1), Ok(7));
+        let end = RAM_SIZE as isize;
+        assert_eq!(vm.read_u8(RAM_SIZE), Err(VmError::MemoryOutOfBounds(end)));
+        assert_eq!(
+            vm.read_i16(RAM_SIZE - 1),
+            Err(VmError::MemoryOutOfBounds(end - 1))
+        );
+        assert_eq!(
+            vm.read_i32(RAM_SIZE - 3),
+            Err(VmError::MemoryOutOfBounds(end - 3))
+        );
+        assert_eq!(
+            vm.write_i32(RAM_SIZE - 2, 1),
+            Err(VmError::MemoryOutOfBounds(end - 2))
+        );
+        assert_eq!(
+            vm.read_u8(RAM_SIZE - 2),
+            Ok(0),
+            "Failed write modified ram!"
+        );
+        assert!(vm.read_i32(usize::MAX).is_err());
+        assert_eq!(
+            vm.read_fix(RAM_SIZE - 3),
+            Err(VmError::MemoryOutOfBounds(end - 3))
+        );
+        assert!(vm.write_fix(RAM_SIZE - 2, Fix16::ONE).is_err());
+    }
+
+    #[test]
+    fn test_regions() {
+        let mut vm = init_vm();
+        assert_eq!(vm.region_kind(0), RegionKind::Ram);
+        assert!(vm.map_region(0x100, 0x100, RegionKind::Rom));
+        assert!(vm.map_region(0x180, 0x10, RegionKind::NoExec));
+        assert!(!vm.map_region(RAM_SIZE - 1, 2, RegionKind::Rom));
+        assert_eq!(vm.region_kind(0xff), RegionKind::Ram);
+        assert_eq!(vm.region_kind(0x100), RegionKind::Rom);
+        assert_eq!(vm.region_kind(0x185), RegionKind::NoExec);
+        assert_eq!(vm.region_kind(0x190), RegionKind::Rom);
+        assert_eq!(vm.region_kind(0x200), RegionKind::Ram);
+        // The host can still write to rom.
+        assert_eq!(vm.write_u8(0x100, 1), Ok(()));
+        vm.clear_regions();
+        assert_eq!(vm.region_kind(0x100), RegionKind::Ram);
+    }
+
+    #[test]
+    fn test_fetch_from_no_exec() {
+        let mut vm = init_vm();
+        let code = [OpCodes::Nop as u8; 4];
+        assert!(vm.load(&code));
+        assert!(vm.map_region(2, 2, RegionKind::NoExec));
+        let result = vm.run(10);
+        assert_eq!(
+            result.reason,
+            StopReason::Faulted(VmError::ProtectionFault(2))
+        );
+        assert_eq!(result.retired, 2);
+        assert_eq!(vm.pc, 2, "PC not left on the faulting instruction.");
+        // Guest handlers can catch protection faults.
+        vm.resume();
+        vm.interrupts[NUM_PORTS + VmError::ProtectionFault(0).code() as usize] = 0;
+        assert_eq!(
+            vm.cycle_once(),
+            Ok(StepOutcome::Trapped(VmError::ProtectionFault(2)))
+        );
+        assert_eq!(
+            vm.data_stack.pop(),
+            Some(5 << 16),
+            "Wrong fault code pushed!"
+        );
+    }
+
+    #[test]
+    fn test_operands_from_no_exec() {
+        // Operand bytes are fetched like the opcode, so one running into the region faults.
+        let ops = [
+            OpCodes::PushImm as u8,
+            OpCodes::JmpImm as u8,
+            OpCodes::PushIndImm as u8,
+        ];
+        for op in ops.iter() {
+            let mut vm = init_vm();
+            assert!(vm.map_region(0x40, 0x10, RegionKind::NoExec));
+            vm.data_stack.push(0);
+            assert!(vm.write_u8(0x3f, *op).is_ok());
+            vm.pc = 0x3f;
+            assert_eq!(vm.cycle_once(), Err(VmError::ProtectionFault(0x40)));
+            assert_eq!(vm.pc, 0x3f, "PC not left on the faulting instruction.");
+            assert_eq!(vm.data_stack.as_slice(), &[0], "Operand consumed!");
+        }
+        // An operand ending right before the region is fine.
+        let mut vm = init_vm();
+        assert!(vm.map_region(0x40, 0x10, RegionKind::NoExec));
+        assert!(vm.write_u8(0x3b, OpCodes::PushImm as u8).is_ok());
+        assert!(vm.write_i32(0x3c, 7).is_ok());
+        vm.pc = 0x3b;
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.data_stack.pop(), Some(7));
+    }
+
+    #[test]
+    fn test_extended_prefix() {
+        let mut vm = init_vm();
+        let code = [OpCodes::Nop as u8, OpCodes::Ext as u8, 0xff];
+        assert!(vm.load(&code));
+        vm.cycle_once().unwrap();
+        // The second byte is reported, but the pc is left on the prefix.
+        assert_eq!(vm.cycle_once(), Err(VmError::InvalidOpcode(0xff, 2)));
+        assert_eq!(vm.pc, 1, "PC not left on the prefix.");
+        // The second byte is fetched like any other instruction byte.
+        vm.resume();
+        assert!(vm.map_region(2, 1, RegionKind::NoExec));
+        assert_eq!(vm.cycle_once(), Err(VmError::ProtectionFault(2)));
+        assert_eq!(vm.pc, 1, "PC not left on the prefix.");
+        vm.resume();
+        vm.pc = RAM_SIZE - 1;
+        assert!(vm.write_u8(RAM_SIZE - 1, OpCodes::Ext as u8).is_ok());
+        assert_eq!(
+            vm.cycle_once(),
+            Err(VmError::MemoryOutOfBounds(RAM_SIZE as isize))
+        );
+        assert_eq!(vm.pc, RAM_SIZE - 1, "PC not left on the prefix.");
+    }
+
+    #[test]
+    fn test_with_config() {
+        assert!(Vm::with_config(VmConfig::new().num_ports(9)).is_none());
+        let config = VmConfig::new().ram_size(0x100).stack_depth(4).num_ports(2);
+        let mut vm = Vm::with_config(config).unwrap();
+        assert_eq!(vm.image().len(), 0x100);
+        assert_eq!(vm.interrupts.len(), 2 + NUM_FAULT_VECTORS);
+        assert!(vm.port_push(1, Fix16::from_bits(1)));
+        assert!(
+            !vm.port_push(2, Fix16::from_bits(1)),
+            "Pushed to a port that doesn't exist!"
+        );
+        assert!(!vm.load(&[0u8; 0x101]), "Loaded past the end of ram!");
+        // Addresses are checked against the configured ram.
+        let mut code = [OpCodes::Nop as u8; 0x100];
+        code[0] = OpCodes::PushStk as u8;
+        assert!(vm.load(&code));
+        let addr = fp::float_to_fix(0xfd as f32);
+        vm.data_stack.push(addr);
+        assert_eq!(vm.cycle_once(), Err(VmError::MemoryOutOfBounds(0xfd)));
+        assert_eq!(vm.data_stack.pop(), Some(addr));
+        vm.resume();
+        vm.pc = 1;
+        let result = vm.run(0x200);
+        assert_eq!(
+            result.reason,
+            StopReason::Faulted(VmError::MemoryOutOfBounds(0x100))
+        );
+        assert_eq!(result.retired, 0xff);
+    }
+
+    #[test]
+    fn test_config_limits() {
+        let config = VmConfig::new().stack_depth(2).num_ports(2);
+        let mut vm = Vm::with_config(config).unwrap();
+        let code = [OpCodes::Dup as u8, OpCodes::PortPush as u8 | 2];
+        assert!(vm.load(&code));
+        vm.data_stack.push(1);
+        vm.data_stack.push(2);
+        assert!(vm.data_stack.full(), "Stack depth not applied!");
+        assert_eq!(vm.cycle_once(), Err(VmError::StackOverflow));
+        vm.resume();
+        vm.pc = 1;
+        assert_eq!(
+            vm.cycle_once(),
+            Err(VmError::InvalidOpcode(OpCodes::PortPush as u8 | 2, 1))
+        );
+        // Fault vectors follow the configured ports.
+        vm.resume();
+        vm.interrupts[2 + VmError::InvalidOpcode(0, 0).code() as usize] = 0x10;
+        vm.data_stack.clear();
+        assert_eq!(
+            vm.cycle_once(),
+            Ok(StepOutcome::Trapped(VmError::InvalidOpcode(
+                OpCodes::PortPush as u8 | 2,
+                1
+            )))
+        );
+        assert_eq!(vm.pc, 0x10);
+    }
+
+    #[test]
+    fn test_stack_accessors() {
+        let mut vm = init_vm();
+        let code = [OpCodes::CallImm as u8, 0, 0, 0x10, 0];
+        assert!(vm.load(&code));
+        vm.data_stack.push(1);
+        vm.data_stack.push(2);
+        assert!(vm.port_push(4, Fix16::from_bits(3)));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.data_stack().as_slice(), &[1, 2]);
+        assert_eq!(vm.call_stack().peek(), Some(fp::float_to_fix(5.0)));
+        assert_eq!(vm.port(4).unwrap().iter().collect::<Vec<i32>>(), vec![3]);
+        assert!(vm.port(NUM_PORTS).is_none());
+        assert_eq!(
+            vm.data_stack_fix().collect::<Vec<Fix16>>(),
+            vec![Fix16::from_bits(2), Fix16::from_bits(1)]
+        );
+        assert_eq!(vm.call_stack_fix().next(), Some(Fix16::from_f32(5.0)));
+        assert_eq!(
+            vm.port_fix(4).unwrap().collect::<Vec<Fix16>>(),
+            vec![Fix16::from_bits(3)]
+        );
+        assert!(vm.port_fix(NUM_PORTS).is_none());
+    }
+
+    #[test]
+    fn test_run_waits_for_port() {
+        let mut vm = init_vm();
+        let code = [OpCodes::PortGet as u8 | 1, OpCodes::Brk as u8];
+        assert!(vm.load(&code));
+        let result = vm.run(10);
+        assert_eq!(result.reason, StopReason::WaitingForPort(1));
+        assert_eq!(result.retired, 0);
+        assert_eq!(vm.pc, 0, "PortGet moved the pc while waiting!");
+        assert!(vm.port_push(1, Fix16::from_bits(5)));
+        let result = vm.run(10);
+        assert_eq!(result.reason, StopReason::Halted);
+        assert_eq!(result.retired, 2);
+        assert_eq!(vm.data_stack.pop(), Some(5));
+    }
+
+    #[test]
+    fn test_fault_state() {
+        let mut vm = init_vm();
+        let code = [OpCodes::Pop as u8, OpCodes::Brk as u8];
+        assert!(vm.load(&code));
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.state(), VmState::Faulted(VmError::StackUnderflow));
+        // Stays faulted until resumed.
+        vm.data_stack.push(1);
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc(), 0);
+        vm.resume();
+        assert_eq!(vm.state(), VmState::Running);
+        assert_eq!(vm.cycle_once(), Ok(StepOutcome::Retired));
+        assert_eq!(vm.pc(), 1);
+    }
+
+    #[test]
+    fn test_run_off_end_of_ram() {
+        let mut vm = init_vm();
+        let code = [OpCodes::Nop as u8; RAM_SIZE];
+        assert!(vm.load(&code));
+        vm.pc = RAM_SIZE - 2;
+        let result = vm.run(100);
+        assert_eq!(
+            result.reason,
+            StopReason::Faulted(VmError::MemoryOutOfBounds(RAM_SIZE as isize))
+        );
+        assert_eq!(result.retired, 2);
+    }
+
+    #[test]
+    fn test_invalid_opcode() {
+        let mut vm = init_vm();
+        let mut code = [OpCodes::Nop as u8; RAM_SIZE];
+        code[3] = 0b000_10101;
+        assert!(vm.load(&code));
+        let result = vm.run(100);
+        assert_eq!(
+            result.reason,
+            StopReason::Faulted(VmError::InvalidOpcode(0b000_10101, 3))
+        );
+        assert_eq!(result.retired, 3);
+        assert_eq!(vm.pc, 3, "PC not left on the faulting instruction.");
+    }
 }
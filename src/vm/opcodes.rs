@@ -1,10 +1,13 @@
-/// Submodule encapsulating opcode enums.
+//! Submodule encapsulating opcode enums.
 
 #[allow(clippy::unusual_byte_groupings)]
 pub enum OpMasks {
     Family = 0b111_00000,
     Type = 0b000_111_00,
     AddrMode = 0b000000_11,
+    SubType = 0b000_11_000,
+    Port = 0b000_00_111,
+    Misc = 0b000_11111,
     Invalid = 0b00000000,
 }
 
@@ -14,18 +17,30 @@ pub enum OpFamily {
     ArithmeticOp,
     BitManipOp,
     PortOp,
+    ControlOp,
+    InterruptOp,
+    MiscOp,
+    MathOp,
+    Extended,
     Invalid,
 }
 
 #[allow(clippy::unusual_byte_groupings)]
 impl From<u8> for OpFamily {
     fn from(a: u8) -> Self {
+        if a == OpCodes::Ext as u8 {
+            return OpFamily::Extended;
+        }
         let a_masked = a & OpMasks::Family as u8;
         match a_masked {
             0b111_000_00 => OpFamily::StackOp,
             0b110_000_00 => OpFamily::ArithmeticOp,
             0b101_000_00 => OpFamily::BitManipOp,
             0b100_000_00 => OpFamily::PortOp,
+            0b011_000_00 => OpFamily::ControlOp,
+            0b010_000_00 => OpFamily::InterruptOp,
+            0b001_000_00 => OpFamily::MiscOp,
+            0b000_000_00 => OpFamily::MathOp,
             _ => OpFamily::Invalid,
         }
     }
@@ -40,6 +55,16 @@ pub enum OpAddrMode {
     Invalid = 0b11111111,
 }
 
+impl OpAddrMode {
+    // Number of data stack values get_addr and get_addr_val consume in this mode.
+    pub fn stack_args(&self) -> usize {
+        match self {
+            OpAddrMode::Immediate => 0,
+            _ => 1,
+        }
+    }
+}
+
 #[allow(clippy::unusual_byte_groupings)]
 impl From<u8> for OpAddrMode {
     fn from(a: u8) -> Self {
@@ -146,20 +171,282 @@ impl From<u8> for BitOpTypes {
 #[allow(clippy::unusual_byte_groupings)]
 pub enum PortOpTypes {
     Push = 0b000_11_000,
+    Pop = 0b000_10_000,
+    Get = 0b000_01_000,
+    Clear = 0b000_00_000,
     Invalid = 0b11111111,
 }
 
 #[allow(clippy::unusual_byte_groupings)]
 impl From<u8> for PortOpTypes {
     fn from(a: u8) -> Self {
-        let a_masked = a & OpMasks::Type as u8;
+        let a_masked = a & OpMasks::SubType as u8;
         match a_masked {
-         0b000_11_000 => PortOpTypes::Push,
+            0b000_11_000 => PortOpTypes::Push,
+            0b000_10_000 => PortOpTypes::Pop,
+            0b000_01_000 => PortOpTypes::Get,
+            0b000_00_000 => PortOpTypes::Clear,
             _ => PortOpTypes::Invalid,
         }
     }
 }
 
+// Control flow family specific enums
+#[allow(clippy::unusual_byte_groupings)]
+pub enum ControlOpTypes {
+    Jmp = 0b000_111_00,
+    Call = 0b000_110_00,
+    Ret = 0b000_101_00,
+    Beq = 0b000_100_00,
+    Bneq = 0b000_011_00,
+    Bgt = 0b000_010_00,
+    Blt = 0b000_001_00,
+    Invalid = 0b11111111,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for ControlOpTypes {
+    fn from(a: u8) -> Self {
+        let a_masked = a & OpMasks::Type as u8;
+        match a_masked {
+            0b000_111_00 => ControlOpTypes::Jmp,
+            0b000_110_00 => ControlOpTypes::Call,
+            0b000_101_00 => ControlOpTypes::Ret,
+            0b000_100_00 => ControlOpTypes::Beq,
+            0b000_011_00 => ControlOpTypes::Bneq,
+            0b000_010_00 => ControlOpTypes::Bgt,
+            0b000_001_00 => ControlOpTypes::Blt,
+            _ => ControlOpTypes::Invalid,
+        }
+    }
+}
+
+// Interrupt family specific enums
+#[allow(clippy::unusual_byte_groupings)]
+pub enum InterruptOpTypes {
+    Seti = 0b000_11_000,
+    Setf = 0b000_10_000,
+    Reti = 0b000_01_000,
+    Ei = 0b000_00_001,
+    Di = 0b000_00_000,
+    Invalid = 0b11111111,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for InterruptOpTypes {
+    fn from(a: u8) -> Self {
+        let a_masked = a & OpMasks::SubType as u8;
+        match a_masked {
+            0b000_11_000 => InterruptOpTypes::Seti,
+            0b000_10_000 => InterruptOpTypes::Setf,
+            0b000_01_000 => match a & OpMasks::Port as u8 {
+                0b000_00_000 => InterruptOpTypes::Reti,
+                _ => InterruptOpTypes::Invalid,
+            },
+            0b000_00_000 => match a & OpMasks::Port as u8 {
+                0b000_00_001 => InterruptOpTypes::Ei,
+                0b000_00_000 => InterruptOpTypes::Di,
+                _ => InterruptOpTypes::Invalid,
+            },
+            _ => InterruptOpTypes::Invalid,
+        }
+    }
+}
+
+// Misc family specific enums
+#[allow(clippy::unusual_byte_groupings)]
+pub enum MiscOpTypes {
+    Brk = 0b000_00001,
+    Nop = 0b000_00000,
+    Invalid = 0b11111111,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for MiscOpTypes {
+    fn from(a: u8) -> Self {
+        let a_masked = a & OpMasks::Misc as u8;
+        match a_masked {
+            0b000_00001 => MiscOpTypes::Brk,
+            0b000_00000 => MiscOpTypes::Nop,
+            _ => MiscOpTypes::Invalid,
+        }
+    }
+}
+
+// Math family specific enums
+#[allow(clippy::unusual_byte_groupings)]
+pub enum MathOpTypes {
+    Sqrt = 0b000_001_00,
+    Sin = 0b000_010_00,
+    Cos = 0b000_011_00,
+    Atan2 = 0b000_100_00,
+    Exp = 0b000_101_00,
+    Log = 0b000_110_00,
+    Invalid = 0b11111111,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for MathOpTypes {
+    fn from(a: u8) -> Self {
+        // Type 000 keeps 0x00 invalid, and the low two bits are reserved.
+        if a & OpMasks::AddrMode as u8 != 0 {
+            return MathOpTypes::Invalid;
+        }
+        let a_masked = a & OpMasks::Type as u8;
+        match a_masked {
+            0b000_001_00 => MathOpTypes::Sqrt,
+            0b000_010_00 => MathOpTypes::Sin,
+            0b000_011_00 => MathOpTypes::Cos,
+            0b000_100_00 => MathOpTypes::Atan2,
+            0b000_101_00 => MathOpTypes::Exp,
+            0b000_110_00 => MathOpTypes::Log,
+            _ => MathOpTypes::Invalid,
+        }
+    }
+}
+
+// Extended instructions are two bytes: the Ext prefix followed by a second opcode byte. The 3
+// most significant bits of the second byte select one of these groups, and the rest select the
+// instruction within it, so new groups can be added without reusing any one byte encoding.
+#[allow(clippy::unusual_byte_groupings)]
+pub enum ExtOpFamily {
+    ArithmeticOp,
+    CompareOp,
+    IntegerOp,
+    AccumulatorOp,
+    Invalid,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for ExtOpFamily {
+    fn from(a: u8) -> Self {
+        let a_masked = a & OpMasks::Family as u8;
+        match a_masked {
+            0b001_00000 => ExtOpFamily::ArithmeticOp,
+            0b010_00000 => ExtOpFamily::CompareOp,
+            0b011_00000 => ExtOpFamily::IntegerOp,
+            0b100_00000 => ExtOpFamily::AccumulatorOp,
+            _ => ExtOpFamily::Invalid,
+        }
+    }
+}
+
+// Extended arithmetic family specific enums
+#[allow(clippy::unusual_byte_groupings)]
+pub enum ExtArithmeticOpTypes {
+    Mod = 0b001_00000,
+    Neg = 0b001_00001,
+    Abs = 0b001_00010,
+    Min = 0b001_00011,
+    Max = 0b001_00100,
+    Floor = 0b001_00101,
+    Ceil = 0b001_00110,
+    Frac = 0b001_00111,
+    Invalid = 0b11111111,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for ExtArithmeticOpTypes {
+    fn from(a: u8) -> Self {
+        match a {
+            0b001_00000 => ExtArithmeticOpTypes::Mod,
+            0b001_00001 => ExtArithmeticOpTypes::Neg,
+            0b001_00010 => ExtArithmeticOpTypes::Abs,
+            0b001_00011 => ExtArithmeticOpTypes::Min,
+            0b001_00100 => ExtArithmeticOpTypes::Max,
+            0b001_00101 => ExtArithmeticOpTypes::Floor,
+            0b001_00110 => ExtArithmeticOpTypes::Ceil,
+            0b001_00111 => ExtArithmeticOpTypes::Frac,
+            _ => ExtArithmeticOpTypes::Invalid,
+        }
+    }
+}
+
+// Comparison family specific enums
+#[allow(clippy::unusual_byte_groupings)]
+pub enum CompareOpTypes {
+    Eq = 0b010_00000,
+    Ne = 0b010_00001,
+    Lt = 0b010_00010,
+    Le = 0b010_00011,
+    Gt = 0b010_00100,
+    Ge = 0b010_00101,
+    Ltu = 0b010_00110,
+    Gtu = 0b010_00111,
+    Invalid = 0b11111111,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for CompareOpTypes {
+    fn from(a: u8) -> Self {
+        match a {
+            0b010_00000 => CompareOpTypes::Eq,
+            0b010_00001 => CompareOpTypes::Ne,
+            0b010_00010 => CompareOpTypes::Lt,
+            0b010_00011 => CompareOpTypes::Le,
+            0b010_00100 => CompareOpTypes::Gt,
+            0b010_00101 => CompareOpTypes::Ge,
+            0b010_00110 => CompareOpTypes::Ltu,
+            0b010_00111 => CompareOpTypes::Gtu,
+            _ => CompareOpTypes::Invalid,
+        }
+    }
+}
+
+// Integer family specific enums
+#[allow(clippy::unusual_byte_groupings)]
+pub enum IntegerOpTypes {
+    Mul = 0b011_00000,
+    Div = 0b011_00001,
+    FromInt = 0b011_00010,
+    ToInt = 0b011_00011,
+    ToIntRounded = 0b011_00100,
+    Add = 0b011_00101,
+    Mod = 0b011_00110,
+    Invalid = 0b11111111,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for IntegerOpTypes {
+    fn from(a: u8) -> Self {
+        match a {
+            0b011_00000 => IntegerOpTypes::Mul,
+            0b011_00001 => IntegerOpTypes::Div,
+            0b011_00010 => IntegerOpTypes::FromInt,
+            0b011_00011 => IntegerOpTypes::ToInt,
+            0b011_00100 => IntegerOpTypes::ToIntRounded,
+            0b011_00101 => IntegerOpTypes::Add,
+            0b011_00110 => IntegerOpTypes::Mod,
+            _ => IntegerOpTypes::Invalid,
+        }
+    }
+}
+
+// Accumulator family specific enums
+#[allow(clippy::unusual_byte_groupings)]
+pub enum AccumulatorOpTypes {
+    Clear = 0b100_00000,
+    Mac = 0b100_00001,
+    Round = 0b100_00010,
+    Push = 0b100_00011,
+    Pop = 0b100_00100,
+    Invalid = 0b11111111,
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+impl From<u8> for AccumulatorOpTypes {
+    fn from(a: u8) -> Self {
+        match a {
+            0b100_00000 => AccumulatorOpTypes::Clear,
+            0b100_00001 => AccumulatorOpTypes::Mac,
+            0b100_00010 => AccumulatorOpTypes::Round,
+            0b100_00011 => AccumulatorOpTypes::Push,
+            0b100_00100 => AccumulatorOpTypes::Pop,
+            _ => AccumulatorOpTypes::Invalid,
+        }
+    }
+}
+
 // All Opcodes
 #[allow(clippy::unusual_byte_groupings)]
 pub enum OpCodes {
@@ -189,4 +476,80 @@ pub enum OpCodes {
     Or = 0b101_010_00,
     Xor = 0b101_001_00,
     Not = 0b101_000_00,
+    PortPush = 0b100_11_000,
+    PortPop = 0b100_10_000,
+    PortGet = 0b100_01_000,
+    PortClear = 0b100_00_000,
+    JmpImm = 0b011_111_11,
+    JmpIndStk = 0b011_111_10,
+    JmpIndImm = 0b011_111_01,
+    JmpStk = 0b011_111_00,
+    CallImm = 0b011_110_11,
+    CallIndStk = 0b011_110_10,
+    CallIndImm = 0b011_110_01,
+    CallStk = 0b011_110_00,
+    Ret = 0b011_101_00,
+    BeqImm = 0b011_100_11,
+    BeqIndStk = 0b011_100_10,
+    BeqIndImm = 0b011_100_01,
+    BeqStk = 0b011_100_00,
+    BneqImm = 0b011_011_11,
+    BneqIndStk = 0b011_011_10,
+    BneqIndImm = 0b011_011_01,
+    BneqStk = 0b011_011_00,
+    BgtImm = 0b011_010_11,
+    BgtIndStk = 0b011_010_10,
+    BgtIndImm = 0b011_010_01,
+    BgtStk = 0b011_010_00,
+    BltImm = 0b011_001_11,
+    BltIndStk = 0b011_001_10,
+    BltIndImm = 0b011_001_01,
+    BltStk = 0b011_001_00,
+    Seti = 0b010_11_000,
+    Setf = 0b010_10_000,
+    Reti = 0b010_01_000,
+    Ei = 0b010_00_001,
+    Di = 0b010_00_000,
+    Brk = 0b001_00001,
+    Nop = 0b001_00000,
+    Sqrt = 0b000_001_00,
+    Sin = 0b000_010_00,
+    Cos = 0b000_011_00,
+    Atan2 = 0b000_100_00,
+    Exp = 0b000_101_00,
+    Log = 0b000_110_00,
+    Ext = 0b000_111_00,
+}
+
+// All extended opcodes, as the byte that follows OpCodes::Ext.
+#[allow(clippy::unusual_byte_groupings)]
+pub enum ExtOpCodes {
+    Mod = 0b001_00000,
+    Neg = 0b001_00001,
+    Abs = 0b001_00010,
+    Min = 0b001_00011,
+    Max = 0b001_00100,
+    Floor = 0b001_00101,
+    Ceil = 0b001_00110,
+    Frac = 0b001_00111,
+    Eq = 0b010_00000,
+    Ne = 0b010_00001,
+    Lt = 0b010_00010,
+    Le = 0b010_00011,
+    Gt = 0b010_00100,
+    Ge = 0b010_00101,
+    Ltu = 0b010_00110,
+    Gtu = 0b010_00111,
+    IMul = 0b011_00000,
+    IDiv = 0b011_00001,
+    IToF = 0b011_00010,
+    FToI = 0b011_00011,
+    FToIR = 0b011_00100,
+    IAdd = 0b011_00101,
+    IMod = 0b011_00110,
+    AClr = 0b100_00000,
+    Mac = 0b100_00001,
+    ARnd = 0b100_00010,
+    APush = 0b100_00011,
+    APop = 0b100_00100,
 }
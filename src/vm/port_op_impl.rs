@@ -1,18 +1,45 @@
-
 use super::opcodes::*;
+use super::VmError;
+use super::{pop, push};
 use crate::stk::Stack;
 use crate::vm::fp;
 
-pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) {
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
     let op_type = PortOpTypes::from(inst);
+    let port = (inst & OpMasks::Port as u8) as usize;
+    if port >= vm.ports.len() {
+        return Err(super::invalid_opcode(vm, inst));
+    }
     match op_type {
         PortOpTypes::Push => {
-            if let Some(val) = vm.data_stack.pop() {
-                let port_val = (inst & 0b00000111) as usize;
-                vm.ports[port_val].push(val);
+            if vm.ports[port].full() {
+                return Err(VmError::StackOverflow);
+            }
+            let val = pop(&mut vm.data_stack)?;
+            push(&mut vm.ports[port], val)
+        }
+        PortOpTypes::Pop => {
+            if let Some(val) = vm.ports[port].peek() {
+                push(&mut vm.data_stack, val)?;
+                vm.ports[port].pop();
+            } else {
+                super::wait_for_port(vm, port);
             }
+            Ok(())
+        }
+        PortOpTypes::Get => {
+            if let Some(val) = vm.ports[port].peek() {
+                push(&mut vm.data_stack, val)?;
+            } else {
+                super::wait_for_port(vm, port);
+            }
+            Ok(())
+        }
+        PortOpTypes::Clear => {
+            vm.ports[port].clear();
+            Ok(())
         }
-        _ => ()
+        _ => Err(super::invalid_opcode(vm, inst)),
     }
 }
 
@@ -21,7 +48,10 @@ mod test {
 
     use super::*;
     use crate::fp;
+    use crate::fp::Fix16;
+    use crate::vm::StepOutcome;
     use crate::vm::Vm;
+    use crate::vm::VmState;
     use crate::vm::INVALID_INTERRUPT;
     use crate::vm::RAM_SIZE;
 
@@ -45,13 +75,98 @@ mod test {
         let val = 0xDEADC0EDu32 as i32;
         vm.data_stack.push(val);
         let mut code = [0u8; RAM_SIZE];
-        code[0] = 0b100_00_000;
+        code[0] = OpCodes::PortPush as u8;
         vm.load(&code);
-        vm.cycle_once();
-        assert!(vm.data_stack.empty(), "PortPush failed to modify data stack!");
+        vm.cycle_once().unwrap();
+        assert!(
+            vm.data_stack.empty(),
+            "PortPush failed to modify data stack!"
+        );
         assert_eq!(vm.pc, 1, "PortPush failed to increment program counter!");
         let port_val = vm.ports[0].pop();
-        assert!(!port_val.is_none(), "PortPush failed to push to port!");
-        assert_eq!(port_val.unwrap(), val, "PortPush pushed wrong value to port!");
+        assert!(port_val.is_some(), "PortPush failed to push to port!");
+        assert_eq!(
+            port_val.unwrap(),
+            val,
+            "PortPush pushed wrong value to port!"
+        );
+    }
+
+    #[test]
+    fn test_port_push_other_port() {
+        let mut vm = init_vm();
+        vm.data_stack.push(66);
+        let code = [OpCodes::PortPush as u8 | 7; 1];
+        vm.load(&code);
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "PortPush failed to increment program counter!");
+        assert!(vm.ports[0].empty(), "PortPush pushed to the wrong port!");
+        assert_eq!(vm.ports[7].pop(), Some(66), "PortPush missed port 7!");
+    }
+
+    #[test]
+    fn test_port_pop() {
+        let mut vm = init_vm();
+        vm.ports[3].push(1);
+        vm.ports[3].push(2);
+        let code = [OpCodes::PortPop as u8 | 3; 3];
+        vm.load(&code);
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "PortPop failed to increment program counter!");
+        assert_eq!(vm.data_stack.peek(), Some(2), "PortPop pushed wrong value!");
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.data_stack.pop(), Some(1), "PortPop pushed wrong value!");
+        assert_eq!(vm.data_stack.pop(), Some(2), "PortPop pushed wrong value!");
+        assert!(vm.ports[3].empty(), "PortPop failed to consume port value!");
+        // Popping an empty port blocks until the host pushes to it.
+        assert_eq!(vm.cycle_once(), Ok(StepOutcome::Waiting(3)));
+        assert_eq!(vm.pc, 2, "PortPop moved the pc while waiting!");
+        assert_eq!(vm.state(), VmState::WaitingForPort(3));
+        assert!(vm.data_stack.empty(), "PortPop pushed from an empty port!");
+        assert_eq!(vm.cycle_once(), Ok(StepOutcome::Waiting(3)));
+        assert_eq!(vm.pc, 2, "PortPop moved the pc while waiting!");
+        assert!(vm.port_push(3, Fix16::from_bits(7)));
+        assert_eq!(vm.cycle_once(), Ok(StepOutcome::Retired));
+        assert_eq!(vm.pc, 3, "PortPop failed to increment program counter!");
+        assert_eq!(vm.state(), VmState::Running, "PortPop still waiting!");
+        assert_eq!(vm.data_stack.pop(), Some(7), "PortPop pushed wrong value!");
+    }
+
+    #[test]
+    fn test_port_get() {
+        let mut vm = init_vm();
+        vm.ports[5].push(1234);
+        let code = [OpCodes::PortGet as u8 | 5; 1];
+        vm.load(&code);
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "PortGet failed to increment program counter!");
+        assert_eq!(
+            vm.data_stack.pop(),
+            Some(1234),
+            "PortGet pushed wrong value!"
+        );
+        assert_eq!(
+            vm.ports[5].pop(),
+            Some(1234),
+            "PortGet consumed the port value!"
+        );
+    }
+
+    #[test]
+    fn test_port_clear() {
+        let mut vm = init_vm();
+        vm.ports[1].push(1);
+        vm.ports[1].push(2);
+        vm.ports[2].push(3);
+        let code = [OpCodes::PortClear as u8 | 1; 1];
+        vm.load(&code);
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.pc, 1, "PortClear failed to increment program counter!");
+        assert!(vm.ports[1].empty(), "PortClear failed to flush the port!");
+        assert_eq!(
+            vm.ports[2].pop(),
+            Some(3),
+            "PortClear flushed the wrong port!"
+        );
     }
 }
@@ -1,95 +1,84 @@
 use super::opcodes::*;
+use super::VmError;
+use super::{pop, push, require};
 use crate::fp;
 use crate::stk::Stack;
 
-pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) {
+pub(super) fn cycle_op(vm: &mut super::Vm, inst: u8) -> Result<(), VmError> {
     let op_type = StackOpTypes::from(inst);
     let addr_mode = OpAddrMode::from(inst);
     match op_type {
         StackOpTypes::Push => op_push(vm, addr_mode),
         StackOpTypes::Store => op_store(vm, addr_mode),
-        StackOpTypes::Pop => {
-            let _ = vm.data_stack.pop();
-        }
+        StackOpTypes::Pop => pop(&mut vm.data_stack).map(|_| ()),
         StackOpTypes::Dup => {
-            if let Some(val) = vm.data_stack.peek() {
-                vm.data_stack.push(val);
-            }
+            let val = vm.data_stack.peek().ok_or(VmError::StackUnderflow)?;
+            push(&mut vm.data_stack, val)
         }
         StackOpTypes::Rot => {
-            let a = vm.data_stack.pop();
-            if a.is_none() {
-                return;
-            }
-            let b = vm.data_stack.pop();
-            if b.is_none() {
-                vm.data_stack.push(a.unwrap());
-                return;
-            }
-            let c = vm.data_stack.pop();
-            if c.is_none() {
-                vm.data_stack.push(b.unwrap());
-                vm.data_stack.push(a.unwrap());
-                return;
-            }
-            vm.data_stack.push(a.unwrap());
-            vm.data_stack.push(c.unwrap());
-            vm.data_stack.push(b.unwrap());
+            require(&vm.data_stack, 3)?;
+            let a = pop(&mut vm.data_stack)?;
+            let b = pop(&mut vm.data_stack)?;
+            let c = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(a);
+            vm.data_stack.push(c);
+            vm.data_stack.push(b);
+            Ok(())
         }
         StackOpTypes::Swap => {
-            let a = vm.data_stack.pop();
-            if a.is_none() {
-                return;
-            }
-            let b = vm.data_stack.pop();
-            if b.is_none() {
-                vm.data_stack.push(a.unwrap());
-                return;
-            }
-            vm.data_stack.push(a.unwrap());
-            vm.data_stack.push(b.unwrap());
+            require(&vm.data_stack, 2)?;
+            let a = pop(&mut vm.data_stack)?;
+            let b = pop(&mut vm.data_stack)?;
+            vm.data_stack.push(a);
+            vm.data_stack.push(b);
+            Ok(())
         }
         StackOpTypes::MovToRts => {
-            if let Some(a) = vm.data_stack.pop() {
-                vm.call_stack.push(a);
+            require(&vm.data_stack, 1)?;
+            if vm.call_stack.full() {
+                return Err(VmError::StackOverflow);
             }
+            let a = pop(&mut vm.data_stack)?;
+            push(&mut vm.call_stack, a)
         }
         StackOpTypes::MovFromRts => {
-            if let Some(a) = vm.call_stack.pop() {
-                vm.data_stack.push(a);
+            require(&vm.call_stack, 1)?;
+            if vm.data_stack.full() {
+                return Err(VmError::StackOverflow);
             }
+            let a = pop(&mut vm.call_stack)?;
+            push(&mut vm.data_stack, a)
         }
-        _ => {}
+        _ => Err(super::invalid_opcode(vm, inst)),
     }
 }
 
-pub fn op_push(vm: &mut super::Vm, addr_mode: OpAddrMode) {
-    let arg: Option<i32> = super::get_addr_val(vm, &addr_mode);
-    if let Some(p_val) = arg {
-        if let OpAddrMode::Stack = addr_mode {
-            let addr = p_val >> 16_isize;
-            if addr >= 0 {
-                let mut val_arr: [u8; 4] = [0; 4];
-                val_arr[0..4].clone_from_slice(&vm.ram[addr as usize..addr as usize + 4]);
-                vm.data_stack.push(i32::from_ne_bytes(val_arr));
-            }
-        } else {
-            vm.data_stack.push(p_val);
-        }
+pub fn op_push(vm: &mut super::Vm, addr_mode: OpAddrMode) -> Result<(), VmError> {
+    require(&vm.data_stack, addr_mode.stack_args())?;
+    if addr_mode.stack_args() == 0 && vm.data_stack.full() {
+        return Err(VmError::StackOverflow);
+    }
+    if let OpAddrMode::Stack = addr_mode {
+        let addr = super::get_addr(vm, &addr_mode, 4, super::Access::Read)? as usize;
+        let val = vm.load_word(addr)?;
+        push(&mut vm.data_stack, val)
+    } else {
+        let val = super::get_addr_val(vm, &addr_mode)?;
+        push(&mut vm.data_stack, val)
     }
 }
 
-pub fn op_store(vm: &mut super::Vm, addr_mode: OpAddrMode) {
-    if let Some(addr) = super::get_addr(vm, &addr_mode) {
-        if let Some(data) = vm.data_stack.pop() {
-            vm.ram[addr as usize..(addr as usize + 4)].clone_from_slice(&data.to_ne_bytes());
-        }
-    }
+pub fn op_store(vm: &mut super::Vm, addr_mode: OpAddrMode) -> Result<(), VmError> {
+    require(&vm.data_stack, addr_mode.stack_args() + 1)?;
+    let addr = super::get_addr(vm, &addr_mode, 4, super::Access::Write)? as usize;
+    let data = pop(&mut vm.data_stack)?;
+    vm.store_word(addr, data)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::vm::RegionKind;
     use crate::vm::Vm;
     use crate::vm::INVALID_INTERRUPT;
     use crate::vm::RAM_SIZE;
@@ -115,14 +104,14 @@ mod test {
         let mut code: [u8; RAM_SIZE] = [0; RAM_SIZE];
         // Push Immediate
         code[0] = OpCodes::PushImm as u8;
-        code[1..5].clone_from_slice(&test_val.to_ne_bytes());
+        code[1..5].clone_from_slice(&test_val.to_le_bytes());
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 5, "Failed to increment program counter.");
         assert!(!vm.data_stack.empty(), "Data stack empty after push.");
         let top_val = vm.data_stack.peek();
         assert!(
-            !top_val.is_none(),
+            top_val.is_some(),
             "Data stack peek returned None on a nonempty stack."
         );
         assert_eq!(
@@ -132,14 +121,18 @@ mod test {
         );
         // End of the rope test (Push Immediate)
         code[RAM_SIZE - 1] = OpCodes::PushImm as u8;
+        assert!(vm.load(&code));
         vm.pc = RAM_SIZE - 1;
-        vm.cycle_once();
         assert_eq!(
-            vm.pc, RAM_SIZE,
-            "PC did not increment for last instruction."
+            vm.cycle_once(),
+            Err(VmError::MemoryOutOfBounds(RAM_SIZE as isize)),
+            "Truncated immediate did not fault."
+        );
+        assert_eq!(
+            vm.pc,
+            RAM_SIZE - 1,
+            "PC not left on the faulting instruction."
         );
-        vm.cycle_once();
-        assert_eq!(vm.pc, RAM_SIZE, "PC WAS MODIFIED AT END OF RAM INSTRUCTION");
     }
 
     #[test]
@@ -154,16 +147,16 @@ mod test {
         let test_val = 666.0;
         let test_val_fp = fp::float_to_fix(test_val);
         code[target_addr as usize..(target_addr as usize + 4)]
-            .clone_from_slice(&test_val_fp.to_ne_bytes());
+            .clone_from_slice(&test_val_fp.to_le_bytes());
         code[0] = OpCodes::PushIndStk as u8;
-        code[1..3].clone_from_slice(&base.to_ne_bytes());
+        code[1..3].clone_from_slice(&base.to_le_bytes());
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 3, "Failed to increment program counter.");
         assert!(!vm.data_stack.empty(), "Data stack empty after push.");
         let top_val = vm.data_stack.peek();
         assert!(
-            !top_val.is_none(),
+            top_val.is_some(),
             "Data stack peek returned None on a nonempty stack."
         );
         assert_eq!(
@@ -184,17 +177,16 @@ mod test {
         vm.data_stack.push(fp::float_to_fix(base as f32));
         let test_val = 666.0;
         let test_val_fp = fp::float_to_fix(test_val);
-        code[target_addr as usize..(target_addr as usize + 4)]
-            .clone_from_slice(&test_val_fp.to_ne_bytes());
+        code[target_addr..(target_addr + 4)].clone_from_slice(&test_val_fp.to_le_bytes());
         code[0] = OpCodes::PushIndImm as u8;
-        code[1..3].clone_from_slice(&offset.to_ne_bytes());
+        code[1..3].clone_from_slice(&offset.to_le_bytes());
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 3, "Failed to increment program counter.");
         assert!(!vm.data_stack.empty(), "Data stack empty after push.");
         let top_val = vm.data_stack.peek();
         assert!(
-            !top_val.is_none(),
+            top_val.is_some(),
             "Data stack peek returned None on a nonempty stack."
         );
         assert_eq!(
@@ -213,16 +205,15 @@ mod test {
         vm.data_stack.push(fp::float_to_fix(target_addr as f32));
         let test_val = 666.0;
         let test_val_fp = fp::float_to_fix(test_val);
-        code[target_addr as usize..(target_addr as usize + 4)]
-            .clone_from_slice(&test_val_fp.to_ne_bytes());
+        code[target_addr..(target_addr + 4)].clone_from_slice(&test_val_fp.to_le_bytes());
         code[0] = OpCodes::PushStk as u8;
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Failed to increment program counter.");
         assert!(!vm.data_stack.empty(), "Data stack empty after push.");
         let top_val = vm.data_stack.peek();
         assert!(
-            !top_val.is_none(),
+            top_val.is_some(),
             "Data stack peek returned None on a nonempty stack."
         );
         assert_eq!(
@@ -236,30 +227,51 @@ mod test {
     fn test_push_end_of_ram() {
         let mut vm = init_vm();
         let mut code: [u8; RAM_SIZE] = [0; RAM_SIZE];
-        code[RAM_SIZE - 1] = OpCodes::PushImm as u8;
-        assert!(vm.load(&code));
-        vm.pc = RAM_SIZE - 1;
-        vm.cycle_once();
-        assert_eq!(
-            vm.pc, RAM_SIZE,
-            "PC not properly incremented in end of ram test: PUSH_IMM"
-        );
-        code[RAM_SIZE - 1] = OpCodes::PushIndStk as u8;
+        vm.data_stack.push(0);
+        for op in [OpCodes::PushImm, OpCodes::PushIndStk, OpCodes::PushIndImm] {
+            code[RAM_SIZE - 1] = op as u8;
+            assert!(vm.load(&code));
+            vm.pc = RAM_SIZE - 1;
+            assert_eq!(
+                vm.cycle_once(),
+                Err(VmError::MemoryOutOfBounds(RAM_SIZE as isize)),
+                "Truncated operand did not fault: {:#x}",
+                code[RAM_SIZE - 1]
+            );
+            assert_eq!(
+                vm.pc,
+                RAM_SIZE - 1,
+                "PC not left on the faulting instruction: {:#x}",
+                code[RAM_SIZE - 1]
+            );
+            assert_eq!(vm.data_stack.len(), 1, "Fault modified the data stack!");
+        }
+    }
+
+    #[test]
+    fn test_push_out_of_bounds() {
+        let mut vm = init_vm();
+        let mut code: [u8; RAM_SIZE] = [0; RAM_SIZE];
+        code[0] = OpCodes::PushStk as u8;
         assert!(vm.load(&code));
-        vm.pc = RAM_SIZE - 1;
-        vm.cycle_once();
-        assert_eq!(
-            vm.pc, RAM_SIZE,
-            "PC not properly incremented in end of ram test: PUSH_IND_STK"
-        );
-        code[RAM_SIZE - 1] = OpCodes::PushIndImm as u8;
+        let addr = fp::float_to_fix(-8.0);
+        vm.data_stack.push(addr);
+        assert_eq!(vm.cycle_once(), Err(VmError::MemoryOutOfBounds(-8)));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.pop(), Some(addr), "Address not restored!");
+        vm.resume();
+        // Indexed addresses are checked too.
+        code[0] = OpCodes::PushIndImm as u8;
+        code[1..3].clone_from_slice(&(-16i16).to_le_bytes());
         assert!(vm.load(&code));
-        vm.pc = RAM_SIZE - 1;
-        vm.cycle_once();
-        assert_eq!(
-            vm.pc, RAM_SIZE,
-            "PC not properly incremented in end of ram test: PUSH_IND_IMM"
-        );
+        let base = fp::float_to_fix(8.0);
+        vm.data_stack.push(base);
+        assert_eq!(vm.cycle_once(), Err(VmError::MemoryOutOfBounds(-8)));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.pop(), Some(base), "Base not restored!");
+        vm.resume();
+        // Nothing to push from.
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
     }
 
     #[test]
@@ -269,17 +281,17 @@ mod test {
         let mut code: [u8; RAM_SIZE] = [0; RAM_SIZE];
         // Store Immediate
         code[0] = OpCodes::StoreImm as u8;
-        code[1..5].clone_from_slice(&test_addr.to_ne_bytes());
+        code[1..5].clone_from_slice(&test_addr.to_le_bytes());
         assert!(vm.load(&code));
         let test_val: i32 = fp::float_to_fix(1234.0);
         vm.data_stack.push(test_val);
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 5, "Failed to increment program counter.");
         assert!(vm.data_stack.empty(), "Data stack not empty after store.");
         let mut chk_val_arr = [0u8; 4];
         chk_val_arr[0..4]
             .clone_from_slice(&vm.ram[(test_addr >> 16) as usize..(test_addr >> 16) as usize + 4]);
-        let chk_val = i32::from_ne_bytes(chk_val_arr);
+        let chk_val = i32::from_le_bytes(chk_val_arr);
         assert_eq!(chk_val, test_val, "Store failed to save value in ram!");
     }
 
@@ -295,14 +307,14 @@ mod test {
         let target_addr = base + 2;
         vm.data_stack.push(offset);
         code[0] = OpCodes::StoreIndStk as u8;
-        code[1..3].clone_from_slice(&base.to_ne_bytes());
+        code[1..3].clone_from_slice(&base.to_le_bytes());
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 3, "Failed to increment program counter.");
         assert!(vm.data_stack.empty(), "Data stack not empty after store.");
         let mut chk_val_arr = [0u8; 4];
         chk_val_arr[0..4].clone_from_slice(&vm.ram[target_addr as usize..target_addr as usize + 4]);
-        let chk_val = i32::from_ne_bytes(chk_val_arr);
+        let chk_val = i32::from_le_bytes(chk_val_arr);
         assert_eq!(
             chk_val, test_val_fp,
             "Value at address not value to be stored!"
@@ -320,14 +332,14 @@ mod test {
         let target_addr = 123 + 2;
         vm.data_stack.push(base);
         code[0] = OpCodes::StoreIndStk as u8;
-        code[1..3].clone_from_slice(&offset.to_ne_bytes());
+        code[1..3].clone_from_slice(&offset.to_le_bytes());
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 3, "Failed to increment program counter.");
         assert!(vm.data_stack.empty(), "Data stack not empty after store.");
         let mut chk_val_arr = [0u8; 4];
         chk_val_arr[0..4].clone_from_slice(&vm.ram[target_addr as usize..target_addr as usize + 4]);
-        let chk_val = i32::from_ne_bytes(chk_val_arr);
+        let chk_val = i32::from_le_bytes(chk_val_arr);
         assert_eq!(
             chk_val, test_val_fp,
             "Value at address not value to be stored!"
@@ -346,18 +358,80 @@ mod test {
         vm.data_stack.push(target_addr_fp);
         code[0] = OpCodes::StoreStk as u8;
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Failed to increment program counter.");
         assert!(vm.data_stack.empty(), "Data stack not empty after store.");
         let mut chk_val_arr = [0u8; 4];
         chk_val_arr[0..4].clone_from_slice(&vm.ram[target_addr as usize..target_addr as usize + 4]);
-        let chk_val = i32::from_ne_bytes(chk_val_arr);
+        let chk_val = i32::from_le_bytes(chk_val_arr);
         assert_eq!(
             chk_val, test_val_fp,
             "Value at address not value to be stored!"
         );
     }
 
+    #[test]
+    fn test_word_straddles_end_of_ram() {
+        // A word starting in the last three bytes of ram faults instead of panicking.
+        let top = (RAM_SIZE - 2) as isize;
+        let addr = fp::float_to_fix(top as f32);
+        let mut vm = init_vm();
+        let code = [OpCodes::PushStk as u8, OpCodes::StoreStk as u8];
+        assert!(vm.load(&code));
+        vm.data_stack.push(addr);
+        assert_eq!(vm.cycle_once(), Err(VmError::MemoryOutOfBounds(top)));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.pop(), Some(addr), "Address not restored!");
+        vm.resume();
+        vm.pc = 1;
+        vm.data_stack.push(1234);
+        vm.data_stack.push(addr);
+        assert_eq!(vm.cycle_once(), Err(VmError::MemoryOutOfBounds(top)));
+        assert_eq!(vm.pc, 1, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.pop(), Some(addr), "Address not restored!");
+        assert_eq!(vm.data_stack.pop(), Some(1234), "Data not restored!");
+        assert_eq!(
+            vm.read_u8(RAM_SIZE - 1),
+            Ok(0),
+            "Store wrote past the fault!"
+        );
+        vm.resume();
+        // Indexed reads are checked against the whole word too.
+        let mut code = [0u8; 3];
+        code[0] = OpCodes::PushIndStk as u8;
+        code[1..3].clone_from_slice(&((RAM_SIZE - 4) as i16).to_le_bytes());
+        assert!(vm.load(&code));
+        vm.pc = 0;
+        let offset = fp::float_to_fix(1.0);
+        vm.data_stack.push(offset);
+        assert_eq!(
+            vm.cycle_once(),
+            Err(VmError::MemoryOutOfBounds((RAM_SIZE - 3) as isize))
+        );
+        assert_eq!(vm.data_stack.pop(), Some(offset), "Offset not restored!");
+    }
+
+    #[test]
+    fn test_store_to_rom() {
+        let mut vm = init_vm();
+        let mut code = [0u8; 5];
+        code[0] = OpCodes::StoreImm as u8;
+        code[1..5].clone_from_slice(&fp::float_to_fix(0xfe as f32).to_le_bytes());
+        assert!(vm.load(&code));
+        assert!(vm.map_region(0x100, 0x10, RegionKind::Rom));
+        vm.data_stack.push(1234);
+        // The store only overlaps the start of the rom, but the whole word is checked.
+        assert_eq!(vm.cycle_once(), Err(VmError::ProtectionFault(0x100)));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
+        assert_eq!(vm.data_stack.peek(), Some(1234), "Data not restored!");
+        assert_eq!(vm.read_i32(0xfe), Ok(0), "Store wrote to rom!");
+        // Stores elsewhere are unaffected.
+        vm.resume();
+        assert!(vm.map_region(0x100, 0x10, RegionKind::Ram));
+        vm.cycle_once().unwrap();
+        assert_eq!(vm.read_i32(0xfe), Ok(1234), "Store failed after remapping!");
+    }
+
     #[test]
     fn test_pop_op() {
         let mut vm = init_vm();
@@ -368,12 +442,12 @@ mod test {
         code[0] = OpCodes::Pop as u8;
         code[1] = OpCodes::Pop as u8;
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Failed to increment program counter.");
         assert!(vm.data_stack.empty(), "Data stack not empty after pop.");
         // Cycle again, make sure things don't fall apart.
-        vm.cycle_once();
-        assert_eq!(vm.pc, 2, "Failed to increment program counter.");
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 1, "PC not left on the faulting instruction.");
         assert!(vm.data_stack.empty(), "Data stack not empty after pop.");
     }
 
@@ -386,13 +460,13 @@ mod test {
         let mut code = [0u8; RAM_SIZE];
         code[0] = OpCodes::Dup as u8;
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Failed to increment program counter.");
         assert!(!vm.data_stack.empty(), "Data stack empty after dup.");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Pop failed after dup!");
+        assert!(top.is_some(), "Pop failed after dup!");
         let next = vm.data_stack.pop();
-        assert!(!next.is_none(), "Second pop failed after dup!");
+        assert!(next.is_some(), "Second pop failed after dup!");
         assert_eq!(top.unwrap(), next.unwrap(), "Dup didn't duplicate!");
     }
 
@@ -411,30 +485,30 @@ mod test {
         vm.data_stack.push(a_fp);
         let code = [OpCodes::Rot as u8; 1];
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Failed to increment program counter.");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Top empty after rot!");
+        assert!(top.is_some(), "Top empty after rot!");
         assert_eq!(top.unwrap(), b_fp, "Unexpected value in stack!");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Top empty after rot!");
+        assert!(top.is_some(), "Top empty after rot!");
         assert_eq!(top.unwrap(), c_fp, "Unexpected value in stack!");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Top empty after rot!");
+        assert!(top.is_some(), "Top empty after rot!");
         assert_eq!(top.unwrap(), a_fp, "Unexpected value in stack!");
-        // If the stack is too low, do nothing.
+        // If the stack is too low, fault and leave it untouched.
         let mut vm = init_vm();
         vm.data_stack.push(c_fp);
         vm.data_stack.push(b_fp);
         let code = [OpCodes::Rot as u8; 1];
         assert!(vm.load(&code));
-        vm.cycle_once();
-        assert_eq!(vm.pc, 1, "Failed to increment program counter.");
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Top empty after rot!");
+        assert!(top.is_some(), "Top empty after rot!");
         assert_eq!(top.unwrap(), b_fp, "Unexpected value in stack!");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Top empty after rot!");
+        assert!(top.is_some(), "Top empty after rot!");
         assert_eq!(top.unwrap(), c_fp, "Unexpected value in stack!");
     }
 
@@ -450,23 +524,23 @@ mod test {
         vm.data_stack.push(a_fp);
         let code = [OpCodes::Swap as u8; 1];
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Failed to increment program counter.");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Top empty after swap!");
+        assert!(top.is_some(), "Top empty after swap!");
         assert_eq!(top.unwrap(), b_fp, "Unexpected value in stack!");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Top empty after rot!");
+        assert!(top.is_some(), "Top empty after rot!");
         assert_eq!(top.unwrap(), a_fp, "Unexpected value in stack!");
-        // If the stack is too low, do nothing.
+        // If the stack is too low, fault and leave it untouched.
         let mut vm = init_vm();
         vm.data_stack.push(b_fp);
         let code = [OpCodes::Rot as u8; 1];
         assert!(vm.load(&code));
-        vm.cycle_once();
-        assert_eq!(vm.pc, 1, "Failed to increment program counter.");
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
         let top = vm.data_stack.pop();
-        assert!(!top.is_none(), "Top empty after rot!");
+        assert!(top.is_some(), "Top empty after rot!");
         assert_eq!(top.unwrap(), b_fp, "Unexpected value in stack!");
     }
 
@@ -478,18 +552,18 @@ mod test {
         vm.data_stack.push(a_fp);
         let code = [OpCodes::MovToRts as u8; 1];
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Failed to increment program counter.");
         assert!(vm.data_stack.empty(), "Data left on data stack.");
         let cs_top = vm.call_stack.peek();
-        assert!(!cs_top.is_none(), "No data on call stack.");
+        assert!(cs_top.is_some(), "No data on call stack.");
         assert_eq!(cs_top.unwrap(), a_fp, "Wrong data on call stack!");
-        // Should do nothing if no data on data stack.
+        // Should fault if there is nothing to move.
         let mut vm = init_vm();
         let code = [OpCodes::MovToRts as u8; 1];
         assert!(vm.load(&code));
-        vm.cycle_once();
-        assert_eq!(vm.pc, 1, "Failed to increment program counter.");
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
         assert!(vm.data_stack.empty(), "Data left on data stack.");
         let cs_top = vm.call_stack.peek();
         assert!(cs_top.is_none(), "Data on call stack.");
@@ -503,18 +577,18 @@ mod test {
         vm.call_stack.push(a_fp);
         let code = [OpCodes::MovFromRts as u8; 1];
         assert!(vm.load(&code));
-        vm.cycle_once();
+        vm.cycle_once().unwrap();
         assert_eq!(vm.pc, 1, "Failed to increment program counter.");
         assert!(vm.call_stack.empty(), "Data left on call stack.");
         let ds_top = vm.data_stack.peek();
-        assert!(!ds_top.is_none(), "No data on data stack.");
+        assert!(ds_top.is_some(), "No data on data stack.");
         assert_eq!(ds_top.unwrap(), a_fp, "Wrong data on data stack!");
-        // Should do nothing if no data on data stack.
+        // Should fault if there is nothing to move.
         let mut vm = init_vm();
         let code = [OpCodes::MovFromRts as u8; 1];
         assert!(vm.load(&code));
-        vm.cycle_once();
-        assert_eq!(vm.pc, 1, "Failed to increment program counter.");
+        assert_eq!(vm.cycle_once(), Err(VmError::StackUnderflow));
+        assert_eq!(vm.pc, 0, "PC not left on the faulting instruction.");
         assert!(vm.call_stack.empty(), "Data left on call stack.");
         let ds_top = vm.data_stack.peek();
         assert!(ds_top.is_none(), "Data on data stack.");